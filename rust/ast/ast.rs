@@ -1,55 +1,95 @@
 use std::fmt;
-use std::cell::RefCell;
 
 use once_cell::sync::Lazy;
 
 use lexer::tokens::{Tag, Token};
 
 pub mod expression;
+pub mod function;
+pub mod ir;
 pub mod statement;
 
-thread_local! {
-static LABEL_COUNTER: RefCell<i64> = RefCell::new(1);
-}
+use expression::Temp;
+use ir::{Cond, Instr};
 
-pub fn new_label() -> i64 {
-  let mut res = 0;
-  LABEL_COUNTER.with(|counter| {
-    res = *counter.borrow();
-    *counter.borrow_mut() = res + 1
-  });
-  res
+/// State of the generation of code for one program: the code generated so
+/// far and the numbering of its labels and temporaries, which starts over
+/// for every context.
+pub struct CodegenContext {
+  code: Vec<Instr>,
+  labels: i64,
+  temps: i32,
+  value_comparisons: bool,
 }
 
-pub fn reset_labels() {
-  LABEL_COUNTER.with(|counter| {
-    *counter.borrow_mut() = 1;
-  });
-}
+impl CodegenContext {
+  pub fn new() -> CodegenContext {
+    CodegenContext { code: Vec::new(), labels: 1, temps: 1, value_comparisons: false }
+  }
 
-pub fn emit_label(s: &mut String, i: i64) {
-  s.push_str(format!("L{}:", i).as_str());
-}
+  /// Makes boolean values be computed by value instructions, as in
+  /// `t1 = i == j`, instead of jumps setting `true` or `false`, for targets
+  /// with set-on-compare instructions.
+  pub fn compare_values(&mut self, on: bool) {
+    self.value_comparisons = on;
+  }
 
-pub fn emit(s: &mut String, st: &str) {
-  s.push_str(format!("\t{}\n", st).as_str());
-}
+  pub fn value_comparisons(&self) -> bool {
+    self.value_comparisons
+  }
+
+  pub fn new_label(&mut self) -> i64 {
+    let label = self.labels;
+    self.labels += 1;
+    label
+  }
+
+  pub fn new_temp(&mut self, typ: &Type) -> Temp {
+    let temp = Temp::new(typ, self.temps);
+    self.temps += 1;
+    temp
+  }
+
+  pub fn code(&self) -> &[Instr] {
+    &self.code
+  }
 
-pub fn emit_jumps(s: &mut String, test: &str, to: i64, from: i64) {
-  if to != 0 && from != 0 {
-    emit(s, format!("if {} goto L{}", test, to).as_str());
-    emit(s, format!("goto L{}", from).as_str());
-  } else if to != 0 {
-    emit(s, format!("if {} goto L{}", test, to).as_str());
-  } else if from != 0 {
-    emit(s, format!("iffalse {} goto L{}", test, from).as_str());
+  pub fn into_code(self) -> Vec<Instr> {
+    self.code
+  }
+
+  pub fn emit(&mut self, instr: Instr) {
+    self.code.push(instr);
+  }
+
+  pub fn emit_label(&mut self, i: i64) {
+    self.emit(Instr::Label(i));
+  }
+
+  pub fn emit_jumps(&mut self, test: Cond, to: i64, from: i64) {
+    if to != 0 && from != 0 {
+      self.emit(Instr::IfGoto { cond: test, label: to });
+      self.emit(Instr::Goto(from));
+    } else if to != 0 {
+      self.emit(Instr::IfGoto { cond: test, label: to });
+    } else if from != 0 {
+      self.emit(Instr::IfFalseGoto { cond: test, label: from });
+    }
   }
 }
 
 #[derive(Debug,Clone)]
 pub enum Type {
   Simple{lexeme: String, width: u8},
-  Array{of: Box<Type>, length: u32}
+  Array{of: Box<Type>, length: u32},
+  /// Array whose length is only known at run time. Its storage is a
+  /// descriptor holding the address of the elements followed by the `int`
+  /// length of each of its dimensions.
+  Dynamic{of: Box<Type>},
+  Record{fields: Vec<(String, Type)>},
+  Pointer{to: Box<Type>},
+  Enum{name: String, enumerators: Vec<(String, i64)>},
+  Alias{name: String, of: Box<Type>}
 }
 
 impl Type {
@@ -69,6 +109,91 @@ impl Type {
     Type::Array { of: Box::new(of), length: size }
   }
 
+  pub fn dynamic(of: Type) -> Type {
+    Type::Dynamic { of: Box::new(of) }
+  }
+
+  /// Number of runtime sized dimensions of an array.
+  pub fn dimensions(&self) -> u32 {
+    match self.resolve() {
+      Type::Dynamic { of } => 1 + of.dimensions(),
+      _ => 0
+    }
+  }
+
+  /// Type of the elements of a runtime sized array.
+  pub fn element(&self) -> &Type {
+    match self.resolve() {
+      Type::Dynamic { of } => of.element(),
+      _ => self
+    }
+  }
+
+  pub fn record(fields: Vec<(String, Type)>) -> Type {
+    Type::Record { fields: fields }
+  }
+
+  pub fn enumeration(name: &str, enumerators: Vec<(String, i64)>) -> Type {
+    Type::Enum { name: name.to_string(), enumerators: enumerators }
+  }
+
+  pub fn alias(name: &str, of: Type) -> Type {
+    Type::Alias { name: name.to_string(), of: Box::new(of) }
+  }
+
+  /// The type behind any aliases naming it.
+  pub fn resolve(&self) -> &Type {
+    match &self {
+      Type::Alias { name: _, of } => of.resolve(),
+      _ => self
+    }
+  }
+
+  pub fn pointer(to: Type) -> Type {
+    Type::Pointer { to: Box::new(to) }
+  }
+
+  /// Type of the `null` literal, which can be assigned to and compared with
+  /// any pointer.
+  pub fn null() -> &'static Type {
+    static TYP: Lazy<Type> = Lazy::new(|| Type::Simple { lexeme: String::from("null"), width: 8 });
+    &*TYP
+  }
+
+  pub fn pointee(&self) -> Option<&Type> {
+    match self.resolve() {
+      Type::Pointer { to } => Some(to),
+      _ => None
+    }
+  }
+
+  /// Looks up a field of a record returning its type and its offset from the
+  /// start of the record.
+  pub fn field(&self, name: &str) -> Option<(Type, u32)> {
+    let fields = match self.resolve() {
+      Type::Record { fields } => fields,
+      _ => return None
+    };
+    let mut offset = 0;
+    for (fname, ftyp) in fields {
+      if fname == name {
+        return Some((ftyp.clone(), offset))
+      }
+      offset += ftyp.width();
+    }
+    None
+  }
+
+  /// Name of the enumerator of an enumeration type with the given value.
+  pub fn enumerator(&self, value: i64) -> Option<&str> {
+    match self.resolve() {
+      Type::Enum { name: _, enumerators } => enumerators.iter()
+        .find(|(_, v)| *v == value)
+        .map(|(name, _)| name.as_str()),
+      _ => None
+    }
+  }
+
   pub fn integer() -> &'static Type {
     static TYP: Lazy<Type> = Lazy::new(|| Type::new(Token::integer()).unwrap());
     &*TYP
@@ -84,6 +209,21 @@ impl Type {
     &*TYP
   }
 
+  pub fn long() -> &'static Type {
+    static TYP: Lazy<Type> = Lazy::new(|| Type::new(Token::long()).unwrap());
+    &*TYP
+  }
+
+  pub fn double() -> &'static Type {
+    static TYP: Lazy<Type> = Lazy::new(|| Type::new(Token::double()).unwrap());
+    &*TYP
+  }
+
+  pub fn byte() -> &'static Type {
+    static TYP: Lazy<Type> = Lazy::new(|| Type::new(Token::byte()).unwrap());
+    &*TYP
+  }
+
   pub fn boolean() -> &'static Type {
     static TYP: Lazy<Type> = Lazy::new(|| Type::new(Token::boolean()).unwrap());
     &*TYP
@@ -94,45 +234,127 @@ impl Type {
       Type::Simple{lexeme, width} =>
         Token::SimpleType(lexeme.clone(), *width),
       Type::Array{of, length} =>
-        Token::Array(Box::new(of.token()), *length)
+        Token::Array(Box::new(of.token()), *length),
+      Type::Dynamic { of: _ } =>
+        Token::Word(self.to_string(), Tag::INDEX),
+      Type::Record { fields: _ } =>
+        Token::Word(String::from("record"), Tag::RECORD),
+      Type::Pointer { to: _ } =>
+        Token::Word(self.to_string(), Tag::POINTER),
+      Type::Enum { name, enumerators: _ } =>
+        Token::Word(name.clone(), Tag::ENUM),
+      Type::Alias { name: _, of } => of.token()
     }
   }
 
   pub fn tag(&self) -> Tag {
     match &self {
       Type::Simple{lexeme: _, width: _} => Tag::BASIC,
-      Type::Array { of: _, length: _ } => Tag::INDEX
+      Type::Array { of: _, length: _ } => Tag::INDEX,
+      Type::Dynamic { of: _ } => Tag::INDEX,
+      Type::Record { fields: _ } => Tag::RECORD,
+      Type::Pointer { to: _ } => Tag::POINTER,
+      Type::Enum { name: _, enumerators: _ } => Tag::ENUM,
+      Type::Alias { name: _, of } => of.tag()
     }
   }
 
   pub fn width(&self) -> u32 {
     match &self {
       Type::Simple{lexeme: _, width} => *width as u32,
-      Type::Array { of, length } => of.width() * length
+      Type::Array { of, length } => of.width() * length,
+      Type::Dynamic { of: _ } => 8 + Type::integer().width() * self.dimensions(),
+      Type::Record { fields } => fields.iter().map(|(_, typ)| typ.width()).sum(),
+      Type::Pointer { to: _ } => 8,
+      Type::Enum { name: _, enumerators: _ } => Type::integer().width(),
+      Type::Alias { name: _, of } => of.width()
     }
   }
 
-  fn is_numeric(&self) -> bool {
-    match &self {
-      Type::Simple{lexeme, width: _} => match lexeme.as_str() {
-        "int" | "float" | "char" => true,
-        _ => false
+  /// Position of a numeric type in the promotion lattice. Unsigned types
+  /// share the rank of their signed counterpart.
+  fn rank(&self) -> Option<u8> {
+    match self.resolve() {
+      Type::Simple{lexeme, width: _} => match lexeme.trim_start_matches("unsigned ") {
+        "byte" => Some(1),
+        "char" => Some(2),
+        "int" => Some(3),
+        "long" => Some(4),
+        "float" => Some(5),
+        "double" => Some(6),
+        _ => None
       },
-      _ => false,
+      _ => None,
     }
   }
 
-  fn max_type(left: &Type, right: &Type) -> Option<Type> {
-    if !left.is_numeric() || !right.is_numeric() {
-      return None
+  fn is_numeric(&self) -> bool {
+    self.rank().is_some()
+  }
+
+  fn is_floating(&self) -> bool {
+    self.rank().map_or(false, |rank| rank >= Type::float().rank().unwrap())
+  }
+
+  fn is_unsigned(&self) -> bool {
+    match self.resolve() {
+      Type::Simple{lexeme, width: _} => lexeme.starts_with("unsigned "),
+      _ => false
     }
-    if left == Type::float() || right == Type::float() {
-      return Some(Type::float().clone())
+  }
+
+  /// Wraps an integer value around the range of an integral type.
+  fn truncate(&self, value: i64) -> i64 {
+    let bits = self.width() * 8;
+    if bits >= 64 {
+      return value
     }
-    if left == Type::integer() || right == Type::integer() {
-      return Some(Type::integer().clone())
+    let value = value & ((1 << bits) - 1);
+    if self.is_unsigned() || value < 1 << (bits - 1) {
+      value
+    } else {
+      value - (1 << bits)
     }
-    Some(Type::ch().clone())
+  }
+
+  fn is_pointer(&self) -> bool {
+    match self.resolve() {
+      Type::Pointer { to: _ } => true,
+      _ => self == Type::null()
+    }
+  }
+
+  pub fn is_integral(&self) -> bool {
+    self.is_numeric() && !self.is_floating() || self.is_enum()
+  }
+
+  fn is_enum(&self) -> bool {
+    match self.resolve() {
+      Type::Enum { name: _, enumerators: _ } => true,
+      _ => false
+    }
+  }
+
+  /// Fails naming both types unless values of `left` and `right` can be
+  /// mixed, as `mixable` tells.
+  pub fn expect_mixable(mixable: bool, left: &Type, right: &Type) -> Result<(), String> {
+    if mixable {
+      return Ok(())
+    }
+    Err(format!("Type error mixing {} and {}", left, right))
+  }
+
+  /// Type both operands are promoted to: the one ranking higher in the
+  /// lattice `byte < char < int < long < float < double`, and between a signed
+  /// and an unsigned type of the same rank, the unsigned one.
+  fn max_type(left: &Type, right: &Type) -> Option<Type> {
+    let (lrank, rrank) = (left.rank()?, right.rank()?);
+    let typ = if lrank > rrank || lrank == rrank && !right.is_unsigned() {
+      left
+    } else {
+      right
+    };
+    Some(typ.resolve().clone())
   }
 }
 
@@ -140,14 +362,27 @@ impl fmt::Display for Type {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     match &self {
       Type::Simple{lexeme, width: _} => write!(f, "{}", lexeme),
-      Type::Array { of, length } => write!(f, "[{}]{}", length, *of)
+      Type::Array { of, length } => write!(f, "[{}]{}", length, *of),
+      Type::Dynamic { of } => write!(f, "[]{}", *of),
+      Type::Record { fields } => {
+        write!(f, "record {{ ")?;
+        for (name, typ) in fields {
+          write!(f, "{} {}; ", typ, name)?;
+        }
+        write!(f, "}}")
+      },
+      Type::Pointer { to } => write!(f, "{}*", *to),
+      Type::Enum { name, enumerators: _ } => write!(f, "{}", name),
+      Type::Alias { name, of: _ } => write!(f, "{}", name)
     }
   }
 }
 
+/// Aliases compare as the type they name.
 impl PartialEq for Type {
   fn eq(&self, other: &Self) -> bool {
-    match &self {
+    let other = other.resolve();
+    match self.resolve() {
       Type::Simple{lexeme, width} => match other {
         Type::Simple{lexeme: olex, width: ow} => width == ow && lexeme == olex,
         _ => false
@@ -155,7 +390,26 @@ impl PartialEq for Type {
       Type::Array { of, length } => match other {
         Type::Array{of: oof, length: olen} => length == olen && of == oof,
         _ => false
-      }
+      },
+      Type::Dynamic { of } => match other {
+        Type::Dynamic { of: oof } => of == oof,
+        _ => false
+      },
+      Type::Record { fields } => match other {
+        Type::Record { fields: ofields } => fields == ofields,
+        _ => false
+      },
+      Type::Pointer { to } => match other {
+        Type::Pointer { to: oto } => to == oto,
+        _ => false
+      },
+      Type::Enum { name, enumerators } => match other {
+        Type::Enum { name: oname, enumerators: oenumerators } =>
+          name == oname && enumerators == oenumerators,
+        _ => false
+      },
+      // `resolve` never returns an alias.
+      Type::Alias { name: _, of: _ } => unreachable!()
     }
   }
 }
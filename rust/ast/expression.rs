@@ -1,30 +1,61 @@
 use std::fmt;
 
-use std::cell::RefCell;
-
-use lexer::tokens::Token;
+use lexer::tokens::{Tag, Token};
 use lexer;
 
-use super::{emit, emit_jumps, emit_label, new_label, Type};
+use super::{CodegenContext, Type};
+use super::function::Signature;
+use super::ir::{Cond, Instr, Operand, Place};
+use super::statement::{check_copy, check_types};
 
 pub trait Expression: fmt::Display {
   fn op(&self) -> &Token;
   fn typ(&self) -> &Type;
 
-  // TODO(sambatyon): This should take a label generator
-  fn generate(&self, b: &mut String) -> Result<Box<dyn Expression>, String> {
+  fn generate(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
     Ok(self.box_clone())
   }
 
-  fn reduce(&self, b: &mut String) -> Result<Box<dyn Expression>, String> {
+  fn reduce(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
     Ok(self.box_clone())
   }
 
-  fn jumps(&self, b: &mut String, to: i64, from: i64) -> Result<(), String> {
-    emit_jumps(b, format!("{}", &self).as_str(), to, from);
+  fn jumps(&self, cx: &mut CodegenContext, to: i64, from: i64) -> Result<(), String> {
+    let x = self.reduce(cx)?;
+    cx.emit_jumps(Cond::Value(operand_of(x.as_ref())?), to, from);
     Ok(())
   }
 
+  /// Operand standing for the expression when it needs no computation.
+  fn operand(&self) -> Option<Operand> {
+    None
+  }
+
+  /// Location designated by a generated lvalue.
+  fn place(&self) -> Option<Place> {
+    None
+  }
+
+  /// Instruction computing the value of a generated expression into `dst`.
+  fn compute(&self, dst: Operand) -> Result<Instr, String> {
+    match self.operand() {
+      Some(src) => Ok(Instr::Assign { dst: dst, src: src }),
+      None => Err(format!("Cannot compute {} in a single instruction", self))
+    }
+  }
+
+  /// Value of the expression when it can be computed at compile time, or
+  /// the error computing it, such as a division by zero.
+  fn fold(&self) -> Result<Option<Constant>, String> {
+    Ok(None)
+  }
+
+  /// Whether evaluating the expression can neither change any state nor
+  /// fail, so it can be evaluated where short-circuiting would skip it.
+  fn is_pure(&self) -> bool {
+    false
+  }
+
   fn box_clone(&self) -> Box<dyn Expression>;
 }
 
@@ -47,6 +78,7 @@ impl Constant {
     match tok {
       Token::Integer(_) => Ok(Constant{token: tok, typ: Type::integer().clone()}),
       Token::Real(_) => Ok(Constant{token: tok, typ: Type::float().clone()}),
+      Token::Literal(value, typ) => Ok(Constant{token: *value, typ: Type::new(&typ)?}),
       t => Err(format!("Invalid parameter: {}", t))
     }
   }
@@ -63,6 +95,64 @@ impl Constant {
   pub fn false_constant() -> Constant {
     Constant{token: Token::false_token().clone(), typ: Type::boolean().clone()}
   }
+
+  pub fn null_constant() -> Constant {
+    Constant{token: Token::null_token().clone(), typ: Type::null().clone()}
+  }
+
+  /// Converts a numeric or enumeration constant at compile time.
+  pub fn cast(&self, typ: &Type) -> Option<Constant> {
+    let scalar = |t: &Type| t.is_numeric() || t.is_enum();
+    if !scalar(&self.typ) || !scalar(typ) {
+      return if &self.typ == typ { Some(self.clone()) } else { None }
+    }
+    let token = match (&self.token, typ.is_floating()) {
+      (Token::Integer(i), true) => Token::Real(*i as f64),
+      (Token::Real(r), false) => Token::Integer(typ.truncate(*r as i64)),
+      (Token::Integer(i), false) => Token::Integer(typ.truncate(*i)),
+      (tok, _) => tok.clone()
+    };
+    Some(Constant { token: token, typ: typ.clone() })
+  }
+
+  /// Whether an integral `typ` holds the integral part of the constant.
+  /// Floating types hold any number.
+  pub fn fits(&self, typ: &Type) -> bool {
+    let value = match &self.token {
+      Token::Integer(i) => *i,
+      Token::Real(r) => *r as i64,
+      _ => return true
+    };
+    typ.is_floating() || typ.truncate(value) == value
+  }
+
+  fn arith(op: &Token, left: &Constant, right: &Constant, typ: &Type) -> Result<Option<Constant>, String> {
+    let token = match (&left.token, &right.token) {
+      (Token::Integer(l), Token::Integer(r)) => {
+        let value = match op {
+          Token::Tok(b'+') => l.checked_add(*r),
+          Token::Tok(b'-') => l.checked_sub(*r),
+          Token::Tok(b'*') => l.checked_mul(*r),
+          Token::Tok(b'/') if *r == 0 => return Err(String::from("Division by zero in constant expression")),
+          Token::Tok(b'/') => l.checked_div(*r),
+          _ => return Ok(None)
+        };
+        match value.filter(|value| typ.truncate(*value) == *value) {
+          Some(value) => Token::Integer(value),
+          None => return Err(String::from("Overflow in constant expression"))
+        }
+      },
+      (Token::Real(l), Token::Real(r)) => Token::Real(match op {
+        Token::Tok(b'+') => l + r,
+        Token::Tok(b'-') => l - r,
+        Token::Tok(b'*') => l * r,
+        Token::Tok(b'/') => l / r,
+        _ => return Ok(None)
+      }),
+      _ => return Ok(None)
+    };
+    Ok(Some(Constant { token: token, typ: typ.clone() }))
+  }
 }
 
 impl Expression for Constant {
@@ -74,15 +164,27 @@ impl Expression for Constant {
     &self.typ
   }
 
-  fn jumps(&self, b: &mut String, to: i64, from: i64) -> Result<(), String> {
+  fn fold(&self) -> Result<Option<Constant>, String> {
+    Ok(Some(self.clone()))
+  }
+
+  fn jumps(&self, cx: &mut CodegenContext, to: i64, from: i64) -> Result<(), String> {
     if self == Constant::true_constant() && to != 0 {
-      emit(b, format!("goto L{}", to).as_str())
+      cx.emit(Instr::Goto(to))
     } else if self == Constant::false_constant() && from != 0 {
-      emit(b, format!("goto L{}", from).as_str())
+      cx.emit(Instr::Goto(from))
     }
     Ok(())
   }
 
+  fn operand(&self) -> Option<Operand> {
+    Some(Operand::Const(self.clone()))
+  }
+
+  fn is_pure(&self) -> bool {
+    true
+  }
+
   fn box_clone(&self) -> Box<dyn Expression> {
     Box::new(self.clone())
   }
@@ -111,16 +213,27 @@ pub struct Identifier {
   id: Token,
   typ: Type,
   offset: i32,
+  global: bool,
 }
 
 impl Identifier {
   pub fn new(id: Token, typ: &Type, offset: i32) -> Identifier {
-    Identifier { id: id, typ: typ.clone(), offset: offset }
+    Identifier { id: id, typ: typ.clone(), offset: offset, global: false }
   }
 
   pub fn new_box(id: Token, typ: &Type, offset: i32) -> Box<Identifier> {
     Box::new(Identifier::new(id, typ, offset))
   }
+
+  /// A variable with static storage, `offset` bytes into the static area
+  /// instead of the frame. It is written as `@name` in the generated code.
+  pub fn global(id: Token, typ: &Type, offset: i32) -> Identifier {
+    Identifier { id: id, typ: typ.clone(), offset: offset, global: true }
+  }
+
+  pub fn is_global(&self) -> bool {
+    self.global
+  }
 }
 
 impl Expression for Identifier {
@@ -132,6 +245,18 @@ impl Expression for Identifier {
     &self.typ
   }
 
+  fn operand(&self) -> Option<Operand> {
+    Some(Operand::Var(self.clone()))
+  }
+
+  fn place(&self) -> Option<Place> {
+    Some(Place::Var(Operand::Var(self.clone())))
+  }
+
+  fn is_pure(&self) -> bool {
+    true
+  }
+
   fn box_clone(&self) -> Box<dyn Expression> {
     Box::new(self.clone())
   }
@@ -139,6 +264,9 @@ impl Expression for Identifier {
 
 impl fmt::Display for Identifier {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.global {
+      return write!(f, "@{}", self.id)
+    }
     write!(f, "{}", self.id)
   }
 }
@@ -150,17 +278,9 @@ pub struct Temp {
   num: i32,
 }
 
-thread_local! {
-static TEMP_COUNTER: RefCell<i32> = RefCell::new(1);
-}
-
 impl Temp {
-  pub fn new(typ: &Type) -> Temp {
-    let mut num: i32 = 0;
-    TEMP_COUNTER.with(|counter|{
-      num = *counter.borrow();
-      *counter.borrow_mut() = num + 1;
-    });
+  /// Temporaries are numbered by the `CodegenContext` they are created in.
+  pub fn new(typ: &Type, num: i32) -> Temp {
     Temp{
       op: Token::temp_word().clone(),
       typ: typ.clone(),
@@ -168,14 +288,8 @@ impl Temp {
     }
   }
 
-  pub fn new_box(typ: &Type) -> Box<Temp> {
-    Box::new(Temp::new(typ))
-  }
-
-  pub fn reset_counter() {
-    TEMP_COUNTER.with(|counter| {
-      *counter.borrow_mut() = 1
-    });
+  pub fn new_box(typ: &Type, num: i32) -> Box<Temp> {
+    Box::new(Temp::new(typ, num))
   }
 }
 
@@ -188,6 +302,14 @@ impl Expression for Temp {
     &self.typ
   }
 
+  fn operand(&self) -> Option<Operand> {
+    Some(Operand::Temp(self.clone()))
+  }
+
+  fn is_pure(&self) -> bool {
+    true
+  }
+
   fn box_clone(&self) -> Box<dyn Expression> {
     Box::new(self.clone())
   }
@@ -199,6 +321,132 @@ impl fmt::Display for Temp {
   }
 }
 
+/// Operand standing for an expression already reduced to a variable,
+/// temporary or constant.
+pub fn operand_of(expr: &dyn Expression) -> Result<Operand, String> {
+  match expr.operand() {
+    Some(operand) => Ok(operand),
+    None => Err(format!("Expected a single operand instead of {}", expr))
+  }
+}
+
+pub fn place_of(expr: &dyn Expression) -> Result<Place, String> {
+  match expr.place() {
+    Some(place) => Ok(place),
+    None => Err(format!("Cannot assign to {}", expr))
+  }
+}
+
+/// Stores the generated `value` into the generated lvalue `target`. A value
+/// stored into a variable is computed right into it, anything else is stored
+/// from a single operand.
+pub fn emit_store(cx: &mut CodegenContext, target: &dyn Expression, value: &dyn Expression) -> Result<(), String> {
+  let instr = match place_of(target)? {
+    Place::Var(var) => value.compute(var)?,
+    place => place.store(operand_of(value)?)
+  };
+  cx.emit(instr);
+  Ok(())
+}
+
+/// Wraps `expr` in the conversion needed to use it where a value of `typ` is
+/// expected. Constant values are converted right away, and only when `typ`
+/// can hold them: unlike an explicit cast, the conversion never wraps around.
+pub fn convert(expr: Box<dyn Expression>, typ: &Type) -> Result<Box<dyn Expression>, String> {
+  if !expr.typ().is_numeric() || !typ.is_numeric() {
+    return Ok(expr)
+  }
+  let folded = expr.fold()?;
+  if let Some(c) = &folded {
+    if !c.fits(typ) {
+      return Err(format!("Constant {} does not fit in {}", c, typ))
+    }
+  }
+  if expr.typ() == typ {
+    return Ok(expr)
+  }
+  match folded.and_then(|c| c.cast(typ)) {
+    Some(c) => Ok(Box::new(c)),
+    None => Ok(Box::new(CastOp { typ: typ.clone(), expr: expr }))
+  }
+}
+
+pub struct CastOp {
+  typ: Type,
+  expr: Box<dyn Expression>,
+}
+
+impl CastOp {
+  /// Numeric and enumeration values convert into each other, and pointers into
+  /// other pointers or integral values and back.
+  pub fn new(typ: &Type, expr: Box<dyn Expression>) -> Result<CastOp, String> {
+    let from = expr.typ();
+    let valid = from == typ
+      || (from.is_numeric() || from.is_enum()) && (typ.is_numeric() || typ.is_enum())
+      || from.is_pointer() && (typ.pointee().is_some() || typ.is_integral())
+      || from.is_integral() && typ.pointee().is_some();
+    if !valid {
+      return Err(format!("Cannot cast {} to {}", from, typ))
+    }
+    Ok(CastOp { typ: typ.clone(), expr: expr })
+  }
+
+  pub fn new_box(typ: &Type, expr: Box<dyn Expression>) -> Result<Box<CastOp>, String> {
+    let co = CastOp::new(typ, expr)?;
+    Ok(Box::new(co))
+  }
+}
+
+impl Expression for CastOp {
+  fn op(&self) -> &Token {
+    Token::cast_word()
+  }
+
+  fn typ(&self) -> &Type {
+    &self.typ
+  }
+
+  fn generate(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    let x = self.expr.reduce(cx)?;
+    Ok(Box::new(CastOp { typ: self.typ.clone(), expr: x }))
+  }
+
+  fn fold(&self) -> Result<Option<Constant>, String> {
+    Ok(self.expr.fold()?.and_then(|c| c.cast(&self.typ)))
+  }
+
+  fn compute(&self, dst: Operand) -> Result<Instr, String> {
+    Ok(Instr::Cast { dst: dst, typ: self.typ.clone(), src: operand_of(self.expr.as_ref())? })
+  }
+
+  fn reduce(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    let x = self.generate(cx)?;
+    let tmp = cx.new_temp(self.typ());
+    cx.emit(x.compute(Operand::Temp(tmp.clone()))?);
+    Ok(Box::new(tmp))
+  }
+
+  fn is_pure(&self) -> bool {
+    self.expr.is_pure()
+  }
+
+  fn box_clone(&self) -> Box<dyn Expression> {
+    Box::new(self.clone())
+  }
+}
+
+impl Clone for CastOp {
+  fn clone(&self) -> Self {
+    CastOp { typ: self.typ.clone(), expr: self.expr.box_clone() }
+  }
+}
+
+impl fmt::Display for CastOp {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "({}) {}", self.typ, self.expr)
+  }
+}
+
 pub struct ArithmeticOp {
   op: Token,
   typ: Type,
@@ -207,11 +455,22 @@ pub struct ArithmeticOp {
 }
 
 impl ArithmeticOp {
+  /// Adding an integral value to a pointer, or subtracting it from one, is
+  /// also accepted. The integral side is expected to be already scaled by the
+  /// width of the pointee.
   pub fn new(tok: Token, left: Box<dyn Expression>, right: Box<dyn Expression>) -> Result<ArithmeticOp, String> {
-    let typ = match Type::max_type(left.typ(), right.typ()) {
-      Some(t) => t,
-      None => return Err(String::from("Type error"))
+    let (lt, rt) = (left.typ(), right.typ());
+    let additive = tok.match_tag(b'+') || tok.match_tag(b'-');
+    let typ = match Type::max_type(lt, rt) {
+      Some(t) => Some(t),
+      None if additive && lt.pointee().is_some() && rt.is_integral() => Some(lt.clone()),
+      None if tok.match_tag(b'+') && lt.is_integral() && rt.pointee().is_some() => Some(rt.clone()),
+      None => None
     };
+    Type::expect_mixable(typ.is_some(), lt, rt)?;
+    let typ = typ.unwrap();
+    let left = convert(left, &typ)?;
+    let right = convert(right, &typ)?;
     Ok(ArithmeticOp{ op: tok, typ: typ, left: left, right: right })
   }
 
@@ -230,22 +489,41 @@ impl Expression for ArithmeticOp {
     &self.typ
   }
 
-  fn generate(&self, b: &mut String) -> Result<Box<dyn Expression>, String> {
-    let lr = self.left.reduce(b)?;
-    let rr = self.right.reduce(b)?;
+  fn generate(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    let lr = self.left.reduce(cx)?;
+    let rr = self.right.reduce(cx)?;
     match ArithmeticOp::new(self.op.clone(), lr, rr) {
       Ok(ao) => Ok(Box::new(ao)),
       Err(s) => Err(s)
     }
   }
 
-  fn reduce(&self, b: &mut String) -> Result<Box<dyn Expression>, String> {
-    let x = self.generate(b)?;
-    let tmp = Temp::new(self.typ());
-    emit(b, format!("{} = {}", tmp, x).as_str());
+  fn fold(&self) -> Result<Option<Constant>, String> {
+    if !self.typ.is_numeric() {
+      return Ok(None)
+    }
+    match (self.left.fold()?, self.right.fold()?) {
+      (Some(left), Some(right)) => Constant::arith(&self.op, &left, &right, &self.typ),
+      _ => Ok(None)
+    }
+  }
+
+  fn compute(&self, dst: Operand) -> Result<Instr, String> {
+    let (left, right) = (operand_of(self.left.as_ref())?, operand_of(self.right.as_ref())?);
+    Ok(Instr::BinOp { dst: dst, op: self.op.clone(), left: left, right: right })
+  }
+
+  fn reduce(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    let x = self.generate(cx)?;
+    let tmp = cx.new_temp(self.typ());
+    cx.emit(x.compute(Operand::Temp(tmp.clone()))?);
     Ok(Box::new(tmp))
   }
 
+  fn is_pure(&self) -> bool {
+    !self.op.match_tag(b'/') && !self.op.match_tag(b'%') && self.left.is_pure() && self.right.is_pure()
+  }
+
   fn box_clone(&self) -> Box<dyn Expression> {
     Box::new(self.clone())
   }
@@ -280,6 +558,7 @@ impl UnaryOp {
       Some(typ) => typ,
       _ => return Err(String::from("Type Error"))
     };
+    let rest = convert(rest, &typ)?;
     Ok(UnaryOp { op: op, typ: typ, rest: rest })
   }
 
@@ -298,19 +577,34 @@ impl Expression for UnaryOp {
     &self.typ
   }
 
-  fn generate(&self, b: &mut String) -> Result<Box<dyn Expression>, String> {
-    let rest = self.rest.reduce(b)?;
+  fn generate(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    let rest = self.rest.reduce(cx)?;
     let unary = UnaryOp::new(self.op.clone(), rest)?;
     Ok(Box::new(unary))
   }
 
-  fn reduce(&self, b: &mut String) -> Result<Box<dyn Expression>, String> {
-    let x = self.generate(b)?;
-    let tmp = Temp::new(self.typ());
-    emit(b, format!("{} = {}", tmp, x).as_str());
+  fn fold(&self) -> Result<Option<Constant>, String> {
+    match (Constant::integer(0).cast(&self.typ), self.rest.fold()?) {
+      (Some(zero), Some(rest)) => Constant::arith(&Token::Tok(b'-'), &zero, &rest, &self.typ),
+      _ => Ok(None)
+    }
+  }
+
+  fn compute(&self, dst: Operand) -> Result<Instr, String> {
+    Ok(Instr::UnOp { dst: dst, op: self.op.clone(), src: operand_of(self.rest.as_ref())? })
+  }
+
+  fn reduce(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    let x = self.generate(cx)?;
+    let tmp = cx.new_temp(self.typ());
+    cx.emit(x.compute(Operand::Temp(tmp.clone()))?);
     Ok(Box::new(tmp))
   }
 
+  fn is_pure(&self) -> bool {
+    self.rest.is_pure()
+  }
+
   fn box_clone(&self) -> Box<dyn Expression> {
     Box::new(self.clone())
   }
@@ -357,24 +651,37 @@ impl Expression for AccessOp {
     &self.typ
   }
 
-  fn generate(&self, b: &mut String) -> Result<Box<dyn Expression>, String> {
-    let idx = self.index.reduce(b)?;
+  fn generate(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    let idx = self.index.reduce(cx)?;
     Ok(Box::new(AccessOp::new(self.array.clone(), idx, &self.typ)))
   }
 
-  fn reduce(&self, b: &mut String) -> Result<Box<dyn Expression>, String> {
-    let x = self.generate(b)?;
-    let tmp = Temp::new(self.typ());
-    emit(b, format!("{} = {}", tmp, x).as_str());
+  fn reduce(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    let x = self.generate(cx)?;
+    let tmp = cx.new_temp(self.typ());
+    cx.emit(x.compute(Operand::Temp(tmp.clone()))?);
     Ok(Box::new(tmp))
   }
 
-  fn jumps(&self, b: &mut String, to: i64, from: i64) -> Result<(), String> {
-    let ra = self.reduce(b)?;
-    emit_jumps(b, format!("{}", ra).as_str(), to, from);
+  fn jumps(&self, cx: &mut CodegenContext, to: i64, from: i64) -> Result<(), String> {
+    let ra = self.reduce(cx)?;
+    cx.emit_jumps(Cond::Value(operand_of(ra.as_ref())?), to, from);
     Ok(())
   }
 
+  fn place(&self) -> Option<Place> {
+    Some(Place::Index(Operand::Var(*self.array.clone()), self.index.operand()?))
+  }
+
+  fn compute(&self, dst: Operand) -> Result<Instr, String> {
+    let index = operand_of(self.index.as_ref())?;
+    Ok(Instr::IndexLoad { dst: dst, array: Operand::Var(*self.array.clone()), index: index })
+  }
+
+  fn is_pure(&self) -> bool {
+    self.index.is_pure()
+  }
+
   fn box_clone(&self) -> Box<dyn Expression> {
     Box::new(self.clone())
   }
@@ -396,6 +703,668 @@ impl fmt::Display for AccessOp {
   }
 }
 
+/// Subscript checked against the length of its dimension before it is used,
+///
+/// ```text
+/// if i < 0 goto L1
+/// if i >= 100 goto L1
+/// goto L2
+/// L1: trap a, 7
+/// L2:
+/// ```
+///
+/// where `trap a, n` stops the program reporting an index out of the bounds
+/// of array `a` at source line `n`.
+pub struct BoundsCheckOp {
+  index: Box<dyn Expression>,
+  length: Box<dyn Expression>,
+  array: String,
+  line: u32,
+}
+
+impl BoundsCheckOp {
+  pub fn new(index: Box<dyn Expression>, length: Box<dyn Expression>, array: &str, line: u32) -> BoundsCheckOp {
+    BoundsCheckOp { index: index, length: length, array: array.to_string(), line: line }
+  }
+
+  pub fn new_box(index: Box<dyn Expression>, length: Box<dyn Expression>, array: &str, line: u32) -> Box<BoundsCheckOp> {
+    Box::new(BoundsCheckOp::new(index, length, array, line))
+  }
+}
+
+impl Expression for BoundsCheckOp {
+  fn op(&self) -> &Token {
+    self.index.op()
+  }
+
+  fn typ(&self) -> &Type {
+    self.index.typ()
+  }
+
+  fn generate(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    let idx = self.index.reduce(cx)?;
+    let len = self.length.reduce(cx)?;
+    let (fail, ok) = (cx.new_label(), cx.new_label());
+    let (index, length) = (operand_of(idx.as_ref())?, operand_of(len.as_ref())?);
+    let zero = Operand::Const(Constant::integer(0));
+    cx.emit(Instr::IfGoto { cond: Cond::Relation(Token::Tok(b'<'), index.clone(), zero), label: fail });
+    cx.emit(Instr::IfGoto { cond: Cond::Relation(Token::Ge, index, length), label: fail });
+    cx.emit(Instr::Goto(ok));
+    cx.emit_label(fail);
+    cx.emit(Instr::Trap { array: Some(self.array.clone()), line: self.line });
+    cx.emit_label(ok);
+    Ok(idx)
+  }
+
+  fn reduce(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    self.generate(cx)
+  }
+
+  fn jumps(&self, cx: &mut CodegenContext, to: i64, from: i64) -> Result<(), String> {
+    let idx = self.reduce(cx)?;
+    cx.emit_jumps(Cond::Value(operand_of(idx.as_ref())?), to, from);
+    Ok(())
+  }
+
+  fn box_clone(&self) -> Box<dyn Expression> {
+    Box::new(self.clone())
+  }
+}
+
+impl Clone for BoundsCheckOp {
+  fn clone(&self) -> Self {
+    BoundsCheckOp {
+      index: self.index.box_clone(),
+      length: self.length.box_clone(),
+      array: self.array.clone(),
+      line: self.line,
+    }
+  }
+}
+
+impl fmt::Display for BoundsCheckOp {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.index)
+  }
+}
+
+pub struct CallOp {
+  sig: Signature,
+  args: Vec<Box<dyn Expression>>,
+  // Set on the call returned by `generate`, whose arguments were already
+  // passed with `param`.
+  passed: bool,
+}
+
+impl CallOp {
+  pub fn new(sig: &Signature, args: Vec<Box<dyn Expression>>) -> Result<CallOp, String> {
+    if sig.params.len() != args.len() {
+      return Err(format!("Wrong number of arguments calling {}", sig))
+    }
+    for (param, arg) in sig.params.iter().zip(args.iter()) {
+      if check_types(param, arg.typ()).is_none() {
+        return Err(format!("Type error calling {}", sig))
+      }
+    }
+    let args = sig.params.iter().zip(args).map(|(param, arg)| convert(arg, param)).collect::<Result<_, _>>()?;
+    Ok(CallOp { sig: sig.clone(), args: args, passed: false })
+  }
+
+  pub fn new_box(sig: &Signature, args: Vec<Box<dyn Expression>>) -> Result<Box<CallOp>, String> {
+    let co = CallOp::new(sig, args)?;
+    Ok(Box::new(co))
+  }
+
+  /// The call instruction, storing the value returned into `dst` if any.
+  pub fn call(&self, dst: Option<Operand>) -> Instr {
+    Instr::Call { dst: dst, name: self.sig.name.to_string(), args: self.args.len() }
+  }
+}
+
+impl Expression for CallOp {
+  fn op(&self) -> &Token {
+    &self.sig.name
+  }
+
+  fn typ(&self) -> &Type {
+    &self.sig.ret
+  }
+
+  fn generate(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    if self.passed {
+      return Ok(self.box_clone())
+    }
+    let mut args = Vec::new();
+    for arg in self.args.iter() {
+      // Generating first turns boolean expressions into a temporary.
+      args.push(arg.generate(cx)?.reduce(cx)?);
+    }
+    for arg in args.iter() {
+      cx.emit(Instr::Param(operand_of(arg.as_ref())?));
+    }
+    Ok(Box::new(CallOp { sig: self.sig.clone(), args: args, passed: true }))
+  }
+
+  fn reduce(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    let x = self.generate(cx)?;
+    let tmp = cx.new_temp(self.typ());
+    cx.emit(x.compute(Operand::Temp(tmp.clone()))?);
+    Ok(Box::new(tmp))
+  }
+
+  fn jumps(&self, cx: &mut CodegenContext, to: i64, from: i64) -> Result<(), String> {
+    let rc = self.reduce(cx)?;
+    cx.emit_jumps(Cond::Value(operand_of(rc.as_ref())?), to, from);
+    Ok(())
+  }
+
+  fn compute(&self, dst: Operand) -> Result<Instr, String> {
+    Ok(self.call(Some(dst)))
+  }
+
+  fn box_clone(&self) -> Box<dyn Expression> {
+    Box::new(self.clone())
+  }
+}
+
+impl Clone for CallOp {
+  fn clone(&self) -> Self {
+    CallOp {
+      sig: self.sig.clone(),
+      args: self.args.iter().map(|arg| arg.box_clone()).collect(),
+      passed: self.passed,
+    }
+  }
+}
+
+impl fmt::Display for CallOp {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "call {}, {}", self.sig.name, self.args.len())
+  }
+}
+
+pub struct AddressOp {
+  typ: Type,
+  expr: Box<dyn Expression>,
+}
+
+impl AddressOp {
+  pub fn new(expr: Box<dyn Expression>) -> Result<AddressOp, String> {
+    if !expr.op().match_tag(Tag::ID) && !expr.op().match_tag(Tag::INDEX) {
+      return Err(format!("Cannot take the address of {}", expr))
+    }
+    Ok(AddressOp { typ: Type::pointer(expr.typ().clone()), expr: expr })
+  }
+
+  pub fn new_box(expr: Box<dyn Expression>) -> Result<Box<AddressOp>, String> {
+    let ao = AddressOp::new(expr)?;
+    Ok(Box::new(ao))
+  }
+}
+
+impl Expression for AddressOp {
+  fn op(&self) -> &Token {
+    Token::address_word()
+  }
+
+  fn typ(&self) -> &Type {
+    &self.typ
+  }
+
+  fn generate(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    let x = self.expr.generate(cx)?;
+    Ok(Box::new(AddressOp { typ: self.typ.clone(), expr: x }))
+  }
+
+  fn reduce(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    let x = self.generate(cx)?;
+    let tmp = cx.new_temp(self.typ());
+    cx.emit(x.compute(Operand::Temp(tmp.clone()))?);
+    Ok(Box::new(tmp))
+  }
+
+  fn compute(&self, dst: Operand) -> Result<Instr, String> {
+    Ok(Instr::Address { dst: dst, place: place_of(self.expr.as_ref())? })
+  }
+
+  fn is_pure(&self) -> bool {
+    self.expr.is_pure()
+  }
+
+  fn box_clone(&self) -> Box<dyn Expression> {
+    Box::new(self.clone())
+  }
+}
+
+impl Clone for AddressOp {
+  fn clone(&self) -> Self {
+    AddressOp { typ: self.typ.clone(), expr: self.expr.box_clone() }
+  }
+}
+
+impl fmt::Display for AddressOp {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "&{}", self.expr)
+  }
+}
+
+/// `new T` and `new T[n]` take storage for one or `n` values of type `T`
+/// from the heap, lowered to `t = alloc size` with the size in bytes. Both
+/// give a pointer to the first value.
+pub struct NewOp {
+  typ: Type,
+  size: Box<dyn Expression>,
+}
+
+impl NewOp {
+  pub fn new(of: &Type, count: Box<dyn Expression>) -> Result<NewOp, String> {
+    if !count.typ().is_integral() {
+      return Err(format!("Number of {} values should be integral", of))
+    }
+    let width = of.width() as i64;
+    let size: Box<dyn Expression> = match count.fold()?.map(|c| c.op().clone()) {
+      Some(Token::Integer(n)) => match n.checked_mul(width) {
+        Some(size) => Box::new(Constant::integer(size)),
+        None => return Err(String::from("Array size too large"))
+      },
+      _ => {
+        let count = convert(count, Type::integer())?;
+        ArithmeticOp::new_box(Token::Tok(b'*'), count, Box::new(Constant::integer(width)))?
+      }
+    };
+    Ok(NewOp { typ: Type::pointer(of.clone()), size: size })
+  }
+
+  pub fn new_box(of: &Type, count: Box<dyn Expression>) -> Result<Box<NewOp>, String> {
+    let n = NewOp::new(of, count)?;
+    Ok(Box::new(n))
+  }
+}
+
+impl Expression for NewOp {
+  fn op(&self) -> &Token {
+    Token::new_word()
+  }
+
+  fn typ(&self) -> &Type {
+    &self.typ
+  }
+
+  fn generate(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    let size = self.size.reduce(cx)?;
+    Ok(Box::new(NewOp { typ: self.typ.clone(), size: size }))
+  }
+
+  fn reduce(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    let x = self.generate(cx)?;
+    let tmp = cx.new_temp(self.typ());
+    cx.emit(x.compute(Operand::Temp(tmp.clone()))?);
+    Ok(Box::new(tmp))
+  }
+
+  fn jumps(&self, cx: &mut CodegenContext, to: i64, from: i64) -> Result<(), String> {
+    let rn = self.reduce(cx)?;
+    cx.emit_jumps(Cond::Value(operand_of(rn.as_ref())?), to, from);
+    Ok(())
+  }
+
+  fn compute(&self, dst: Operand) -> Result<Instr, String> {
+    Ok(Instr::Alloc { dst: dst, size: operand_of(self.size.as_ref())? })
+  }
+
+  fn box_clone(&self) -> Box<dyn Expression> {
+    Box::new(self.clone())
+  }
+}
+
+impl Clone for NewOp {
+  fn clone(&self) -> Self {
+    NewOp { typ: self.typ.clone(), size: self.size.box_clone() }
+  }
+}
+
+impl fmt::Display for NewOp {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "alloc {}", self.size)
+  }
+}
+
+pub struct DerefOp {
+  typ: Type,
+  expr: Box<dyn Expression>,
+}
+
+impl DerefOp {
+  pub fn new(expr: Box<dyn Expression>) -> Result<DerefOp, String> {
+    let typ = match expr.typ().pointee() {
+      Some(typ) => typ.clone(),
+      None => return Err(String::from("Type error"))
+    };
+    Ok(DerefOp { typ: typ, expr: expr })
+  }
+
+  pub fn new_box(expr: Box<dyn Expression>) -> Result<Box<DerefOp>, String> {
+    let d = DerefOp::new(expr)?;
+    Ok(Box::new(d))
+  }
+}
+
+impl Expression for DerefOp {
+  fn op(&self) -> &Token {
+    Token::deref_word()
+  }
+
+  fn typ(&self) -> &Type {
+    &self.typ
+  }
+
+  fn generate(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    let x = self.expr.reduce(cx)?;
+    Ok(Box::new(DerefOp { typ: self.typ.clone(), expr: x }))
+  }
+
+  fn reduce(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    let x = self.generate(cx)?;
+    let tmp = cx.new_temp(self.typ());
+    cx.emit(x.compute(Operand::Temp(tmp.clone()))?);
+    Ok(Box::new(tmp))
+  }
+
+  fn jumps(&self, cx: &mut CodegenContext, to: i64, from: i64) -> Result<(), String> {
+    let rd = self.reduce(cx)?;
+    cx.emit_jumps(Cond::Value(operand_of(rd.as_ref())?), to, from);
+    Ok(())
+  }
+
+  fn place(&self) -> Option<Place> {
+    Some(Place::Deref(self.expr.operand()?))
+  }
+
+  fn compute(&self, dst: Operand) -> Result<Instr, String> {
+    Ok(Instr::Load { dst: dst, ptr: operand_of(self.expr.as_ref())? })
+  }
+
+  fn box_clone(&self) -> Box<dyn Expression> {
+    Box::new(self.clone())
+  }
+}
+
+impl Clone for DerefOp {
+  fn clone(&self) -> Self {
+    DerefOp { typ: self.typ.clone(), expr: self.expr.box_clone() }
+  }
+}
+
+impl fmt::Display for DerefOp {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "*{}", self.expr)
+  }
+}
+
+/// Whether `expr` designates storage that can be assigned to. Generating such
+/// an expression evaluates its subscripts or pointer once and yields the
+/// location, which reads as the stored value when reduced.
+pub fn is_lvalue(expr: &dyn Expression) -> bool {
+  let op = expr.op();
+  op.match_tag(Tag::ID) || op.match_tag(Tag::INDEX) || op.match_tag(Tag::DEREF)
+}
+
+/// Prefix and postfix `++` and `--`.
+pub struct IncDecOp {
+  op: Token,
+  target: Box<dyn Expression>,
+  prefix: bool,
+}
+
+impl IncDecOp {
+  pub fn new(op: Token, target: Box<dyn Expression>, prefix: bool) -> Result<IncDecOp, String> {
+    if !is_lvalue(target.as_ref()) {
+      return Err(format!("Cannot assign to {}", target))
+    }
+    if !target.typ().is_numeric() && target.typ().pointee().is_none() {
+      return Err(String::from("Type error"))
+    }
+    Ok(IncDecOp { op: op, target: target, prefix: prefix })
+  }
+
+  pub fn new_box(op: Token, target: Box<dyn Expression>, prefix: bool) -> Result<Box<IncDecOp>, String> {
+    let ido = IncDecOp::new(op, target, prefix)?;
+    Ok(Box::new(ido))
+  }
+
+  // Pointers move to the next or previous pointee.
+  fn step(&self) -> Box<dyn Expression> {
+    let step = match self.target.typ().pointee() {
+      Some(to) => to.width() as i64,
+      None => 1
+    };
+    Box::new(Constant::integer(step))
+  }
+}
+
+impl Expression for IncDecOp {
+  fn op(&self) -> &Token {
+    &self.op
+  }
+
+  fn typ(&self) -> &Type {
+    self.target.typ()
+  }
+
+  fn generate(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    let target = self.target.generate(cx)?;
+    let is_id = target.op().match_tag(Tag::ID);
+    let old = if is_id && !self.prefix {
+      let tmp = cx.new_temp(self.typ());
+      cx.emit(Instr::Assign { dst: Operand::Temp(tmp.clone()), src: operand_of(target.as_ref())? });
+      Box::new(tmp)
+    } else {
+      target.reduce(cx)?
+    };
+
+    let op = if self.op.match_tag(Tag::INC) { Token::Tok(b'+') } else { Token::Tok(b'-') };
+    let current = if is_id { target.box_clone() } else { old.box_clone() };
+    let update = convert(ArithmeticOp::new_box(op, current, self.step())?, self.typ())?;
+    let new = if is_id { update.generate(cx)? } else { update.reduce(cx)? };
+    emit_store(cx, target.as_ref(), new.as_ref())?;
+
+    if !self.prefix {
+      return Ok(old)
+    }
+    if is_id {
+      return Ok(target)
+    }
+    Ok(new)
+  }
+
+  fn reduce(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    self.generate(cx)
+  }
+
+  fn box_clone(&self) -> Box<dyn Expression> {
+    Box::new(self.clone())
+  }
+}
+
+impl Clone for IncDecOp {
+  fn clone(&self) -> Self {
+    IncDecOp { op: self.op.clone(), target: self.target.box_clone(), prefix: self.prefix }
+  }
+}
+
+impl fmt::Display for IncDecOp {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if self.prefix {
+      write!(f, "{}{}", self.op, self.target)
+    } else {
+      write!(f, "{}{}", self.target, self.op)
+    }
+  }
+}
+
+/// Assignment used as an expression. Its value is the one stored, typed as
+/// the target.
+pub struct AssignOp {
+  target: Box<dyn Expression>,
+  expr: Box<dyn Expression>,
+}
+
+impl AssignOp {
+  pub fn new(target: Box<dyn Expression>, expr: Box<dyn Expression>) -> Result<AssignOp, String> {
+    if !is_lvalue(target.as_ref()) {
+      return Err(format!("Cannot assign to {}", target))
+    }
+    Type::expect_mixable(check_types(target.typ(), expr.typ()).is_some(), target.typ(), expr.typ())?;
+    let expr = convert(expr, target.typ())?;
+    Ok(AssignOp { target: target, expr: expr })
+  }
+
+  pub fn new_box(target: Box<dyn Expression>, expr: Box<dyn Expression>) -> Result<Box<AssignOp>, String> {
+    let ao = AssignOp::new(target, expr)?;
+    Ok(Box::new(ao))
+  }
+}
+
+impl Expression for AssignOp {
+  fn op(&self) -> &Token {
+    Token::assign_word()
+  }
+
+  fn typ(&self) -> &Type {
+    self.target.typ()
+  }
+
+  // A variable is read back after the store, anything else reuses the stored
+  // temporary so that its location is not computed again.
+  fn generate(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    let target = self.target.generate(cx)?;
+    if target.op().match_tag(Tag::ID) {
+      let value = self.expr.generate(cx)?;
+      emit_store(cx, target.as_ref(), value.as_ref())?;
+      return Ok(target)
+    }
+    let value = self.expr.reduce(cx)?;
+    emit_store(cx, target.as_ref(), value.as_ref())?;
+    Ok(value)
+  }
+
+  fn reduce(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    self.generate(cx)
+  }
+
+  fn jumps(&self, cx: &mut CodegenContext, to: i64, from: i64) -> Result<(), String> {
+    let ra = self.reduce(cx)?;
+    cx.emit_jumps(Cond::Value(operand_of(ra.as_ref())?), to, from);
+    Ok(())
+  }
+
+  fn box_clone(&self) -> Box<dyn Expression> {
+    Box::new(self.clone())
+  }
+}
+
+impl Clone for AssignOp {
+  fn clone(&self) -> Self {
+    AssignOp { target: self.target.box_clone(), expr: self.expr.box_clone() }
+  }
+}
+
+impl fmt::Display for AssignOp {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} = {}", self.target, self.expr)
+  }
+}
+
+/// `cond ? left : right`, where only the selected arm is evaluated.
+pub struct CondOp {
+  typ: Type,
+  cond: Box<dyn Expression>,
+  left: Box<dyn Expression>,
+  right: Box<dyn Expression>,
+}
+
+impl CondOp {
+  pub fn new(cond: Box<dyn Expression>, left: Box<dyn Expression>, right: Box<dyn Expression>) -> Result<CondOp, String> {
+    if cond.typ() != Type::boolean() {
+      return Err(String::from("Conditional expression should be of bool type"))
+    }
+    let (lt, rt) = (left.typ(), right.typ());
+    let typ = match Type::max_type(lt, rt) {
+      Some(typ) => Some(typ),
+      None if lt == rt => Some(lt.clone()),
+      None if lt.pointee().is_some() && rt == Type::null() => Some(lt.clone()),
+      None if lt == Type::null() && rt.pointee().is_some() => Some(rt.clone()),
+      None => None
+    };
+    Type::expect_mixable(typ.is_some(), lt, rt)?;
+    let typ = typ.unwrap();
+    let left = convert(left, &typ)?;
+    let right = convert(right, &typ)?;
+    Ok(CondOp { typ: typ, cond: cond, left: left, right: right })
+  }
+
+  pub fn new_box(cond: Box<dyn Expression>, left: Box<dyn Expression>, right: Box<dyn Expression>) -> Result<Box<CondOp>, String> {
+    let co = CondOp::new(cond, left, right)?;
+    Ok(Box::new(co))
+  }
+}
+
+impl Expression for CondOp {
+  fn op(&self) -> &Token {
+    Token::cond_word()
+  }
+
+  fn typ(&self) -> &Type {
+    &self.typ
+  }
+
+  fn generate(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    let f = cx.new_label();
+    let a = cx.new_label();
+    let tmp = cx.new_temp(self.typ());
+    self.cond.jumps(cx, 0, f)?;
+    let left = self.left.generate(cx)?;
+    cx.emit(left.compute(Operand::Temp(tmp.clone()))?);
+    cx.emit(Instr::Goto(a));
+    cx.emit_label(f);
+    let right = self.right.generate(cx)?;
+    cx.emit(right.compute(Operand::Temp(tmp.clone()))?);
+    cx.emit_label(a);
+    Ok(Box::new(tmp))
+  }
+
+  fn reduce(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    self.generate(cx)
+  }
+
+  fn jumps(&self, cx: &mut CodegenContext, to: i64, from: i64) -> Result<(), String> {
+    let rc = self.reduce(cx)?;
+    cx.emit_jumps(Cond::Value(operand_of(rc.as_ref())?), to, from);
+    Ok(())
+  }
+
+  fn box_clone(&self) -> Box<dyn Expression> {
+    Box::new(self.clone())
+  }
+}
+
+impl Clone for CondOp {
+  fn clone(&self) -> Self {
+    CondOp {
+      typ: self.typ.clone(),
+      cond: self.cond.box_clone(),
+      left: self.left.box_clone(),
+      right: self.right.box_clone(),
+    }
+  }
+}
+
+impl fmt::Display for CondOp {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} ? {} : {}", self.cond, self.left, self.right)
+  }
+}
+
 pub struct RelationOp {
   op: Token,
   left: Box<dyn Expression>,
@@ -403,16 +1372,31 @@ pub struct RelationOp {
 }
 
 impl RelationOp {
+  /// Numeric operands of different types are compared after promoting the
+  /// narrower one, following the same rules as arithmetic.
   pub fn new(op: Token, left: Box<dyn Expression>, right: Box<dyn Expression>) -> Result<RelationOp, String> {
-    if left.typ() != right.typ() {
-      return Err(String::from("Type error"));
+    let (lt, rt) = (left.typ(), right.typ());
+    let null = lt.is_pointer() && rt == Type::null() || lt == Type::null() && rt.is_pointer();
+    let promoted = Type::max_type(lt, rt);
+    Type::expect_mixable(lt == rt || null || promoted.is_some(), lt, rt)?;
+    let (left, right) = match promoted {
+      Some(typ) => (convert(left, &typ)?, convert(right, &typ)?),
+      None => (left, right)
+    };
+    let equality = op.match_tag(Tag::EQ) || op.match_tag(Tag::NE);
+    if equality && check_copy(left.typ(), right.typ()) {
+      return Ok(RelationOp { op: op, left: left, right: right })
     }
-    match left.typ() {
+    match left.typ().resolve() {
       Type::Array { of: _, length: _ } => return Err(String::from("Type error")),
+      Type::Dynamic { of: _ } => return Err(String::from("Type error")),
+      Type::Record { fields: _ } => return Err(String::from("Type error")),
       _ => ()
     }
-    match right.typ() {
+    match right.typ().resolve() {
       Type::Array { of: _, length: _ } => return Err(String::from("Type error")),
+      Type::Dynamic { of: _ } => return Err(String::from("Type error")),
+      Type::Record { fields: _ } => return Err(String::from("Type error")),
       _ => ()
     }
 
@@ -434,26 +1418,65 @@ impl Expression for RelationOp {
     Type::boolean()
   }
 
-  fn generate(&self, b: &mut String) -> Result<Box<dyn Expression>, String> {
-    let f = new_label();
-    let a = new_label();
-    let tmp = Temp::new(self.typ());
-    self.jumps(b, 0, f)?;
-    emit(b, format!("{} = true", tmp).as_str());
-    emit(b, format!("goto L{}", a).as_str());
-    emit_label(b, f);
-    emit(b, format!("{} = false", tmp).as_str());
-    emit_label(b, a);
+  fn generate(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    if cx.value_comparisons() && !check_copy(self.left.typ(), self.right.typ()) {
+      let lr = self.left.reduce(cx)?;
+      let rr = self.right.reduce(cx)?;
+      return Ok(Box::new(RelationOp { op: self.op.clone(), left: lr, right: rr }))
+    }
+    let f = cx.new_label();
+    let a = cx.new_label();
+    let tmp = cx.new_temp(self.typ());
+    self.jumps(cx, 0, f)?;
+    cx.emit(Instr::Assign { dst: Operand::Temp(tmp.clone()), src: Operand::Const(Constant::true_constant()) });
+    cx.emit(Instr::Goto(a));
+    cx.emit_label(f);
+    cx.emit(Instr::Assign { dst: Operand::Temp(tmp.clone()), src: Operand::Const(Constant::false_constant()) });
+    cx.emit_label(a);
     Ok(Box::new(tmp))
   }
 
-  fn jumps(&self, b: &mut String, to: i64, from: i64) -> Result<(), String> {
-    let lr = self.left.reduce(b)?;
-    let rr = self.right.reduce(b)?;
-    emit_jumps(b, format!("{} {} {}", lr, self.op, rr).as_str(), to, from);
+  fn reduce(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    if cx.value_comparisons() && !check_copy(self.left.typ(), self.right.typ()) {
+      let x = self.generate(cx)?;
+      return emit_value(cx, x.as_ref())
+    }
+    Ok(self.box_clone())
+  }
+
+  /// Arrays are compared as blocks of bytes by `t = equal x, y, n`, which is
+  /// true when the `n` bytes at both locations are the same.
+  fn jumps(&self, cx: &mut CodegenContext, to: i64, from: i64) -> Result<(), String> {
+    if check_copy(self.left.typ(), self.right.typ()) {
+      let lr = self.left.generate(cx)?;
+      let rr = self.right.generate(cx)?;
+      let tmp = cx.new_temp(Type::boolean());
+      let (left, right) = (place_of(lr.as_ref())?, place_of(rr.as_ref())?);
+      cx.emit(Instr::Equal { dst: Operand::Temp(tmp.clone()), left: left, right: right, size: lr.typ().width() });
+      let test = Cond::Value(Operand::Temp(tmp));
+      if self.op.match_tag(Tag::EQ) {
+        cx.emit_jumps(test, to, from);
+      } else {
+        cx.emit_jumps(test, from, to);
+      }
+      return Ok(())
+    }
+    let lr = self.left.reduce(cx)?;
+    let rr = self.right.reduce(cx)?;
+    let (left, right) = (operand_of(lr.as_ref())?, operand_of(rr.as_ref())?);
+    cx.emit_jumps(Cond::Relation(self.op.clone(), left, right), to, from);
     Ok(())
   }
 
+  fn compute(&self, dst: Operand) -> Result<Instr, String> {
+    let (left, right) = (operand_of(self.left.as_ref())?, operand_of(self.right.as_ref())?);
+    Ok(Instr::BinOp { dst: dst, op: self.op.clone(), left: left, right: right })
+  }
+
+  fn is_pure(&self) -> bool {
+    self.left.is_pure() && self.right.is_pure()
+  }
+
   fn box_clone(&self) -> Box<dyn Expression> {
     Box::new(self.clone())
   }
@@ -506,21 +1529,41 @@ impl Expression for NotLogicOp {
     Type::boolean()
   }
 
-  fn generate(&self, b: &mut String) -> Result<Box<dyn Expression>, String> {
-    let from = new_label();
-    let a = new_label();
-    let tmp = Temp::new(self.typ());
-    self.jumps(b, 0, from)?;
-    emit(b, format!("{} = true", tmp).as_str());
-    emit(b, format!("goto L{}", a).as_str());
-    emit_label(b, from);
-    emit(b, format!("{} = false", tmp).as_str());
-    emit_label(b, a);
+  fn generate(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    if cx.value_comparisons() {
+      let x = self.expr.generate(cx)?.reduce(cx)?;
+      return Ok(Box::new(NotLogicOp { op: self.op.clone(), expr: x }))
+    }
+    let from = cx.new_label();
+    let a = cx.new_label();
+    let tmp = cx.new_temp(self.typ());
+    self.jumps(cx, 0, from)?;
+    cx.emit(Instr::Assign { dst: Operand::Temp(tmp.clone()), src: Operand::Const(Constant::true_constant()) });
+    cx.emit(Instr::Goto(a));
+    cx.emit_label(from);
+    cx.emit(Instr::Assign { dst: Operand::Temp(tmp.clone()), src: Operand::Const(Constant::false_constant()) });
+    cx.emit_label(a);
     Ok(Box::new(tmp))
   }
 
-  fn jumps(&self, b: &mut String, to: i64, from: i64) -> Result<(), String> {
-    self.expr.jumps(b, from, to)
+  fn reduce(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    if cx.value_comparisons() {
+      let x = self.generate(cx)?;
+      return emit_value(cx, x.as_ref())
+    }
+    Ok(self.box_clone())
+  }
+
+  fn jumps(&self, cx: &mut CodegenContext, to: i64, from: i64) -> Result<(), String> {
+    self.expr.jumps(cx, from, to)
+  }
+
+  fn compute(&self, dst: Operand) -> Result<Instr, String> {
+    Ok(Instr::UnOp { dst: dst, op: self.op.clone(), src: operand_of(self.expr.as_ref())? })
+  }
+
+  fn is_pure(&self) -> bool {
+    self.expr.is_pure()
   }
 
   fn box_clone(&self) -> Box<dyn Expression> {
@@ -541,6 +1584,15 @@ impl fmt::Display for NotLogicOp {
   }
 }
 
+// Computes a boolean with a single value instruction, as in `t1 = i < j`.
+// `&&` and `||` are only computed this way when both operands are pure, as
+// skipping the right one is then unobservable.
+fn emit_value(cx: &mut CodegenContext, value: &dyn Expression) -> Result<Box<dyn Expression>, String> {
+  let tmp = cx.new_temp(Type::boolean());
+  cx.emit(value.compute(Operand::Temp(tmp.clone()))?);
+  Ok(Box::new(tmp))
+}
+
 fn check_booleans(tleft: &Type, tright: &Type) -> bool {
   let bt = Type::boolean();
   tleft == bt && tright == bt
@@ -574,32 +1626,54 @@ impl Expression for OrLogicOp {
     Type::boolean()
   }
 
-  fn generate(&self, b: &mut String) -> Result<Box<dyn Expression>, String> {
-    let from = new_label();
-    let a = new_label();
-    let tmp = Temp::new(self.typ());
-    self.jumps(b, 0, from)?;
-    emit(b, format!("{} = true", tmp).as_str());
-    emit(b, format!("goto L{}", a).as_str());
-    emit_label(b, from);
-    emit(b, format!("{} = false", tmp).as_str());
-    emit_label(b, a);
+  fn generate(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    if cx.value_comparisons() && self.left.is_pure() && self.right.is_pure() {
+      let lr = self.left.generate(cx)?.reduce(cx)?;
+      let rr = self.right.generate(cx)?.reduce(cx)?;
+      return Ok(Box::new(OrLogicOp { left: lr, right: rr }))
+    }
+    let from = cx.new_label();
+    let a = cx.new_label();
+    let tmp = cx.new_temp(self.typ());
+    self.jumps(cx, 0, from)?;
+    cx.emit(Instr::Assign { dst: Operand::Temp(tmp.clone()), src: Operand::Const(Constant::true_constant()) });
+    cx.emit(Instr::Goto(a));
+    cx.emit_label(from);
+    cx.emit(Instr::Assign { dst: Operand::Temp(tmp.clone()), src: Operand::Const(Constant::false_constant()) });
+    cx.emit_label(a);
     Ok(Box::new(tmp))
   }
 
-  fn jumps(&self, b: &mut String, to: i64, from: i64) -> Result<(), String> {
+  fn reduce(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    if cx.value_comparisons() && self.left.is_pure() && self.right.is_pure() {
+      let x = self.generate(cx)?;
+      return emit_value(cx, x.as_ref())
+    }
+    Ok(self.box_clone())
+  }
+
+  fn jumps(&self, cx: &mut CodegenContext, to: i64, from: i64) -> Result<(), String> {
     let mut label = to;
     if to == 0 {
-      label = new_label();
+      label = cx.new_label();
     }
-    self.left.jumps(b, label, 0)?;
-    self.right.jumps(b, to, from)?;
+    self.left.jumps(cx, label, 0)?;
+    self.right.jumps(cx, to, from)?;
     if to == 0 {
-      emit_label(b, label);
+      cx.emit_label(label);
     }
     Ok(())
   }
 
+  fn compute(&self, dst: Operand) -> Result<Instr, String> {
+    let (left, right) = (operand_of(self.left.as_ref())?, operand_of(self.right.as_ref())?);
+    Ok(Instr::BinOp { dst: dst, op: Token::or_word().clone(), left: left, right: right })
+  }
+
+  fn is_pure(&self) -> bool {
+    self.left.is_pure() && self.right.is_pure()
+  }
+
   fn box_clone(&self) -> Box<dyn Expression> {
     Box::new(self.clone())
   }
@@ -645,32 +1719,54 @@ impl Expression for AndLogicOp {
     Type::boolean()
   }
 
-  fn generate(&self, b: &mut String) -> Result<Box<dyn Expression>, String> {
-    let from = new_label();
-    let a = new_label();
-    let tmp = Temp::new(self.typ());
-    self.jumps(b, 0, from)?;
-    emit(b, format!("{} = true", tmp).as_str());
-    emit(b, format!("goto L{}", a).as_str());
-    emit_label(b, from);
-    emit(b, format!("{} = false", tmp).as_str());
-    emit_label(b, a);
+  fn generate(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    if cx.value_comparisons() && self.left.is_pure() && self.right.is_pure() {
+      let lr = self.left.generate(cx)?.reduce(cx)?;
+      let rr = self.right.generate(cx)?.reduce(cx)?;
+      return Ok(Box::new(AndLogicOp { left: lr, right: rr }))
+    }
+    let from = cx.new_label();
+    let a = cx.new_label();
+    let tmp = cx.new_temp(self.typ());
+    self.jumps(cx, 0, from)?;
+    cx.emit(Instr::Assign { dst: Operand::Temp(tmp.clone()), src: Operand::Const(Constant::true_constant()) });
+    cx.emit(Instr::Goto(a));
+    cx.emit_label(from);
+    cx.emit(Instr::Assign { dst: Operand::Temp(tmp.clone()), src: Operand::Const(Constant::false_constant()) });
+    cx.emit_label(a);
     Ok(Box::new(tmp))
   }
 
-  fn jumps(&self, b: &mut String, to: i64, from: i64) -> Result<(), String> {
+  fn reduce(&self, cx: &mut CodegenContext) -> Result<Box<dyn Expression>, String> {
+    if cx.value_comparisons() && self.left.is_pure() && self.right.is_pure() {
+      let x = self.generate(cx)?;
+      return emit_value(cx, x.as_ref())
+    }
+    Ok(self.box_clone())
+  }
+
+  fn jumps(&self, cx: &mut CodegenContext, to: i64, from: i64) -> Result<(), String> {
     let mut label = from;
     if from == 0 {
-      label = new_label();
+      label = cx.new_label();
     }
-    self.left.jumps(b, 0, label)?;
-    self.right.jumps(b, to, from)?;
+    self.left.jumps(cx, 0, label)?;
+    self.right.jumps(cx, to, from)?;
     if from == 0 {
-      emit_label(b, label);
+      cx.emit_label(label);
     }
     Ok(())
   }
 
+  fn compute(&self, dst: Operand) -> Result<Instr, String> {
+    let (left, right) = (operand_of(self.left.as_ref())?, operand_of(self.right.as_ref())?);
+    Ok(Instr::BinOp { dst: dst, op: Token::and_word().clone(), left: left, right: right })
+  }
+
+  fn is_pure(&self) -> bool {
+    self.left.is_pure() && self.right.is_pure()
+  }
+
   fn box_clone(&self) -> Box<dyn Expression> {
     Box::new(self.clone())
   }
@@ -691,7 +1787,8 @@ impl Clone for AndLogicOp {
 
 #[cfg(test)]
 mod test {
-use crate::reset_labels;
+use crate::CodegenContext;
+use crate::ir::print;
 use super::*;
 
 use lexer::tokens::Token;
@@ -706,7 +1803,7 @@ fn expression_tests() {
       ""
     ),
     (
-      Temp::new_box(Type::integer()),
+      Temp::new_box(Type::integer(), 1),
       "t1",
       "",
       ""
@@ -721,6 +1818,15 @@ fn expression_tests() {
       "",
       "\tt1 = x + y\n"
     ),
+    (
+      CastOp::new_box(
+        Type::float(),
+        Identifier::new_box(Token::from_str("x"), Type::integer(), 4)
+      ).unwrap(),
+      "(float) x",
+      "",
+      "\tt1 = (float) x\n"
+    ),
     (
       UnaryOp::new_box(
         Token::Tok('-' as u8),
@@ -740,6 +1846,22 @@ fn expression_tests() {
       "",
       "\tt1 = arr [ x ]\n"
     ),
+    (
+      AddressOp::new_box(
+        Identifier::new_box(Token::from_str("x"), Type::integer(), 4),
+      ).unwrap(),
+      "&x",
+      "",
+      "\tt1 = &x\n"
+    ),
+    (
+      DerefOp::new_box(
+        Identifier::new_box(Token::from_str("p"), &Type::pointer(Type::integer().clone()), 4),
+      ).unwrap(),
+      "*p",
+      "",
+      "\tt1 = *p\n"
+    ),
     (
       NotLogicOp::new_box(
         Token::Tok('!' as u8),
@@ -767,6 +1889,16 @@ fn expression_tests() {
       "\tiffalse x goto L1\n\tiffalse y goto L1\n\tt1 = true\n\tgoto L2\nL1:\tt1 = false\nL2:",
       "",
     ),
+    (
+      CondOp::new_box(
+        Identifier::new_box(Token::from_str("b"), Type::boolean(), 4),
+        Identifier::new_box(Token::from_str("x"), Type::integer(), 4),
+        Identifier::new_box(Token::from_str("y"), Type::integer(), 4),
+      ).unwrap(),
+      "b ? x : y",
+      "\tiffalse b goto L1\n\tt1 = x\n\tgoto L2\nL1:\tt1 = y\nL2:",
+      "\tiffalse b goto L3\n\tt2 = x\n\tgoto L4\nL3:\tt2 = y\nL4:",
+    ),
     (
       RelationOp::new_box(
         Token::eq_word().clone(),
@@ -780,16 +1912,17 @@ fn expression_tests() {
   ];
 
   for tc in tests {
-    Temp::reset_counter();
-    reset_labels();
-
     assert_eq!(format!("{}", tc.0), tc.1);
+    let mut cx = CodegenContext::new();
+    tc.0.generate(&mut cx).expect("Generating code");
     let mut b = String::new();
-    tc.0.generate(&mut b).expect("Generating code");
+    print(cx.code(), &mut b);
     assert_eq!(b, tc.2);
 
+    let start = cx.code().len();
+    tc.0.reduce(&mut cx).expect("Reduce step");
     let mut b = String::new();
-    tc.0.reduce(&mut b).expect("Reduce step");
+    print(&cx.code()[start..], &mut b);
     assert_eq!(b, tc.3);
   }
 }
@@ -0,0 +1,64 @@
+use std::fmt;
+
+use lexer::tokens::Token;
+
+use crate::{CodegenContext, Type};
+use super::expression::Identifier;
+use super::ir::Instr;
+use super::statement::Statement;
+
+#[derive(Clone)]
+pub struct Signature {
+  pub name: Token,
+  pub params: Vec<Type>,
+  pub ret: Type,
+}
+
+impl Signature {
+  pub fn new(name: Token, params: Vec<Type>, ret: &Type) -> Signature {
+    Signature { name: name, params: params, ret: ret.clone() }
+  }
+}
+
+impl fmt::Display for Signature {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let params: Vec<String> = self.params.iter().map(|p| p.to_string()).collect();
+    write!(f, "{} {}({})", self.ret, self.name, params.join(", "))
+  }
+}
+
+/// A top level function definition. Its code starts at an entry label named
+/// after the function. The parser makes sure every path through the body
+/// returns a value.
+pub struct Function {
+  pub signature: Signature,
+  params: Vec<Identifier>,
+  body: Box<dyn Statement>,
+  frame: i64,
+}
+
+impl Function {
+  pub fn new(signature: Signature, params: Vec<Identifier>, body: Box<dyn Statement>, frame: i64) -> Function {
+    Function { signature: signature, params: params, body: body, frame: frame }
+  }
+
+  pub fn params(&self) -> &[Identifier] {
+    &self.params
+  }
+
+  /// Storage used by the parameters and locals of the function.
+  pub fn frame(&self) -> i64 {
+    self.frame
+  }
+
+  pub fn generate(&mut self, cx: &mut CodegenContext) -> Result<(), String> {
+    cx.emit(Instr::Entry(self.signature.name.clone()));
+    let begin = cx.new_label();
+    let after = cx.new_label();
+    cx.emit_label(begin);
+    self.body.generate(cx, begin, after)?;
+    cx.emit_label(after);
+    cx.emit(Instr::Return(None));
+    Ok(())
+  }
+}
@@ -0,0 +1,188 @@
+use std::fmt;
+
+use lexer::tokens::Token;
+
+use crate::Type;
+use super::expression::{Constant, Identifier, Temp};
+
+/// Value an instruction reads or writes without further computation.
+#[derive(Clone)]
+pub enum Operand {
+  Var(Identifier),
+  Temp(Temp),
+  Const(Constant),
+}
+
+impl fmt::Display for Operand {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self {
+      Operand::Var(id) => write!(f, "{}", id),
+      Operand::Temp(tmp) => write!(f, "{}", tmp),
+      Operand::Const(c) => write!(f, "{}", c),
+    }
+  }
+}
+
+/// Storage an instruction writes to: a variable, an element of an array at
+/// a byte offset, or the target of a pointer.
+#[derive(Clone)]
+pub enum Place {
+  Var(Operand),
+  Index(Operand, Operand),
+  Deref(Operand),
+}
+
+impl Place {
+  /// Instruction storing `src` into the place.
+  pub fn store(self, src: Operand) -> Instr {
+    match self {
+      Place::Var(dst) => Instr::Assign { dst: dst, src: src },
+      Place::Index(array, index) => Instr::IndexStore { array: array, index: index, src: src },
+      Place::Deref(ptr) => Instr::Store { ptr: ptr, src: src },
+    }
+  }
+}
+
+impl fmt::Display for Place {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self {
+      Place::Var(var) => write!(f, "{}", var),
+      Place::Index(array, index) => write!(f, "{} [ {} ]", array, index),
+      Place::Deref(ptr) => write!(f, "*{}", ptr),
+    }
+  }
+}
+
+/// Condition of a conditional jump.
+#[derive(Clone)]
+pub enum Cond {
+  Value(Operand),
+  Relation(Token, Operand, Operand),
+}
+
+impl fmt::Display for Cond {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self {
+      Cond::Value(value) => write!(f, "{}", value),
+      Cond::Relation(op, left, right) => write!(f, "{} {} {}", left, op, right),
+    }
+  }
+}
+
+/// A three-address instruction.
+#[derive(Clone)]
+pub enum Instr {
+  /// `global @x, n` reserves `n` bytes of static storage for `@x`.
+  Global { var: Operand, size: u32 },
+  /// Entry point of a function, named after it.
+  Entry(Token),
+  Label(i64),
+  Assign { dst: Operand, src: Operand },
+  BinOp { dst: Operand, op: Token, left: Operand, right: Operand },
+  UnOp { dst: Operand, op: Token, src: Operand },
+  Cast { dst: Operand, typ: Type, src: Operand },
+  IndexLoad { dst: Operand, array: Operand, index: Operand },
+  IndexStore { array: Operand, index: Operand, src: Operand },
+  Load { dst: Operand, ptr: Operand },
+  Store { ptr: Operand, src: Operand },
+  Address { dst: Operand, place: Place },
+  /// `copy dst, src, n` moves `n` bytes.
+  Copy { dst: Place, src: Place, size: u32 },
+  /// `t = equal x, y, n` compares `n` bytes.
+  Equal { dst: Operand, left: Place, right: Place, size: u32 },
+  Goto(i64),
+  IfGoto { cond: Cond, label: i64 },
+  IfFalseGoto { cond: Cond, label: i64 },
+  /// `goto [ L1, L2, ... ] [ i ]` jumps to the label at position `i`.
+  JumpTable { labels: Vec<i64>, index: Operand },
+  Param(Operand),
+  Call { dst: Option<Operand>, name: String, args: usize },
+  Return(Option<Operand>),
+  Alloc { dst: Operand, size: Operand },
+  Alloca { dst: Place, size: Operand },
+  Free(Operand),
+  /// Stops the program reporting a failed assertion, or an index out of the
+  /// bounds of an array, at a source line.
+  Trap { array: Option<String>, line: u32 },
+  Halt,
+}
+
+impl fmt::Display for Instr {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match &self {
+      Instr::Global { var, size } => write!(f, "global {}, {}", var, size),
+      Instr::Entry(name) => write!(f, "{}:", name),
+      Instr::Label(label) => write!(f, "L{}:", label),
+      Instr::Assign { dst, src } => write!(f, "{} = {}", dst, src),
+      Instr::BinOp { dst, op, left, right } => write!(f, "{} = {} {} {}", dst, left, op, right),
+      Instr::UnOp { dst, op, src } => write!(f, "{} = {} {}", dst, op, src),
+      Instr::Cast { dst, typ, src } => write!(f, "{} = ({}) {}", dst, typ, src),
+      Instr::IndexLoad { dst, array, index } => write!(f, "{} = {} [ {} ]", dst, array, index),
+      Instr::IndexStore { array, index, src } => write!(f, "{} [ {} ] = {}", array, index, src),
+      Instr::Load { dst, ptr } => write!(f, "{} = *{}", dst, ptr),
+      Instr::Store { ptr, src } => write!(f, "*{} = {}", ptr, src),
+      Instr::Address { dst, place } => write!(f, "{} = &{}", dst, place),
+      Instr::Copy { dst, src, size } => write!(f, "copy {}, {}, {}", dst, src, size),
+      Instr::Equal { dst, left, right, size } => write!(f, "{} = equal {}, {}, {}", dst, left, right, size),
+      Instr::Goto(label) => write!(f, "goto L{}", label),
+      Instr::IfGoto { cond, label } => write!(f, "if {} goto L{}", cond, label),
+      Instr::IfFalseGoto { cond, label } => write!(f, "iffalse {} goto L{}", cond, label),
+      Instr::JumpTable { labels, index } => {
+        let labels: Vec<String> = labels.iter().map(|label| format!("L{}", label)).collect();
+        write!(f, "goto [ {} ] [ {} ]", labels.join(", "), index)
+      },
+      Instr::Param(value) => write!(f, "param {}", value),
+      Instr::Call { dst: Some(dst), name, args } => write!(f, "{} = call {}, {}", dst, name, args),
+      Instr::Call { dst: None, name, args } => write!(f, "call {}, {}", name, args),
+      Instr::Return(Some(value)) => write!(f, "return {}", value),
+      Instr::Return(None) => write!(f, "return"),
+      Instr::Alloc { dst, size } => write!(f, "{} = alloc {}", dst, size),
+      Instr::Alloca { dst, size } => write!(f, "{} = alloca {}", dst, size),
+      Instr::Free(ptr) => write!(f, "free {}", ptr),
+      Instr::Trap { array: Some(array), line } => write!(f, "trap {}, {}", array, line),
+      Instr::Trap { array: None, line } => write!(f, "trap {}", line),
+      Instr::Halt => write!(f, "halt"),
+    }
+  }
+}
+
+/// Writes code in its textual form: labels are written at the start of the
+/// line of the instruction following them, and every instruction on its own
+/// tab-indented line.
+pub fn print(code: &[Instr], s: &mut String) {
+  for instr in code {
+    match instr {
+      Instr::Entry(_) | Instr::Label(_) => s.push_str(instr.to_string().as_str()),
+      _ => s.push_str(format!("\t{}\n", instr).as_str()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+use super::*;
+
+#[test]
+fn print_tests() {
+  let x = || Operand::Var(Identifier::new(Token::from_str("x"), Type::integer(), 4));
+  let a = || Operand::Var(Identifier::new(Token::from_str("a"), &Type::array(Type::integer().clone(), 4), 8));
+  let four = || Operand::Const(Constant::integer(4));
+  let tests: Vec<(Instr, &str)> = vec![
+    (Instr::Label(3), "L3:"),
+    (Instr::BinOp { dst: x(), op: Token::Tok(b'+'), left: x(), right: four() }, "\tx = x + 4\n"),
+    (Instr::IndexStore { array: a(), index: four(), src: x() }, "\ta [ 4 ] = x\n"),
+    (Instr::Address { dst: x(), place: Place::Index(a(), four()) }, "\tx = &a [ 4 ]\n"),
+    (Instr::Copy { dst: Place::Deref(x()), src: Place::Var(a()), size: 16 }, "\tcopy *x, a, 16\n"),
+    (Instr::IfFalseGoto { cond: Cond::Relation(Token::Le, x(), four()), label: 2 }, "\tiffalse x <= 4 goto L2\n"),
+    (Instr::JumpTable { labels: vec![4, 5], index: x() }, "\tgoto [ L4, L5 ] [ x ]\n"),
+    (Instr::Call { dst: None, name: String::from("f"), args: 0 }, "\tcall f, 0\n"),
+    (Instr::Trap { array: Some(String::from("a")), line: 7 }, "\ttrap a, 7\n"),
+  ];
+
+  for tc in tests {
+    let mut s = String::new();
+    print(&[tc.0], &mut s);
+    assert_eq!(s, tc.1);
+  }
+}
+}
@@ -1,15 +1,26 @@
-use crate::{emit, emit_label, new_label, Type};
-use super::expression::{AccessOp, Identifier, Expression};
+use std::cell::Cell;
+use std::rc::Rc;
+
+use lexer::tokens::{Tag, Token};
+
+use crate::{CodegenContext, Type};
+use super::expression::{convert, emit_store, is_lvalue, operand_of, place_of, AccessOp, ArithmeticOp, CallOp, Constant, Identifier, Expression};
+use super::ir::{Cond, Instr, Operand, Place};
 
 pub trait Statement {
-  // TODO(sambatyon): This should take a label generator
-  fn generate(&mut self, b: &mut String, begin: i64, after: i64) -> Result<(), String>;
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String>;
 
   fn after(&mut self, label: i64) {}
 
   fn is_null(&self) -> bool {
     false
   }
+
+  /// Whether every path through the statement ends in a return, so that
+  /// control never flows off its end.
+  fn returns(&self) -> bool {
+    false
+  }
 }
 
 pub struct NullStmt {}
@@ -25,7 +36,7 @@ impl NullStmt {
 }
 
 impl Statement for NullStmt {
-  fn generate(&mut self, b: &mut String, being: i64, after: i64) -> Result<(), String> {
+  fn generate(&mut self, cx: &mut CodegenContext, being: i64, after: i64) -> Result<(), String> {
       Ok(())
   }
   fn is_null(&self) -> bool {
@@ -42,13 +53,11 @@ impl AssignStmt {
   pub fn new(id: Box<Identifier>, expr: Box<dyn Expression>) -> Result<AssignStmt, String> {
     let idt = id.typ();
     let ext = expr.typ();
-    if idt.is_numeric() && !ext.is_numeric() || !idt.is_numeric() && ext.is_numeric() {
-      return Err(String::from("Type Error"));
-    }
-    let bt = Type::boolean();
-    if idt == bt && ext != bt || idt != bt && ext == bt {
-      return Err(String::from("Type Error"));
+    if check_copy(idt, ext) {
+      return Ok(AssignStmt { id: id, expr: expr })
     }
+    Type::expect_mixable(check_types(idt, ext).is_some(), idt, ext)?;
+    let expr = convert(expr, id.typ())?;
     Ok(AssignStmt { id: id, expr: expr })
   }
 
@@ -59,22 +68,47 @@ impl AssignStmt {
 }
 
 impl Statement for AssignStmt {
-  fn generate(&mut self, b: &mut String, begin: i64, after: i64) -> Result<(), String> {
-    let expr = self.expr.generate(b)?;
-    emit(b, format!("{} = {}", self.id, expr).as_str());
-    Ok(())
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String> {
+    let expr = self.expr.generate(cx)?;
+    if check_copy(self.id.typ(), expr.typ()) {
+      return emit_copy(cx, place_of(self.id.as_ref())?, expr.as_ref())
+    }
+    emit_store(cx, self.id.as_ref(), expr.as_ref())
+  }
+}
+
+/// Whether a value of type `tright` is stored into `tleft` as a block of
+/// bytes: both are the same array type.
+pub fn check_copy(tleft: &Type, tright: &Type) -> bool {
+  match tleft.resolve() {
+    Type::Array { of: _, length: _ } => tleft == tright,
+    _ => false
   }
 }
 
-fn check_types(tleft: &Type, tright: &Type) -> Option<Type> {
-  match tleft {
+/// Copies a whole array from the location `src` to `dst` with a single
+/// `copy dst, src, n` instruction moving its `n` bytes.
+fn emit_copy(cx: &mut CodegenContext, dst: Place, src: &dyn Expression) -> Result<(), String> {
+  cx.emit(Instr::Copy { dst: dst, src: place_of(src)?, size: src.typ().width() });
+  Ok(())
+}
+
+pub fn check_types(tleft: &Type, tright: &Type) -> Option<Type> {
+  match tleft.resolve() {
     Type::Array { of, length } => return None,
+    Type::Dynamic { of } => return None,
+    Type::Record { fields } => return None,
     _ => ()
   };
-  match tright {
+  match tright.resolve() {
     Type::Array { of, length } => return None,
+    Type::Dynamic { of } => return None,
+    Type::Record { fields } => return None,
     _ => ()
   };
+  if tleft.is_pointer() && tright == Type::null() {
+    return Some(tright.clone());
+  }
   if tleft == tright || (tleft.is_numeric() && tright.is_numeric()) {
     return Some(tright.clone());
   }
@@ -85,17 +119,19 @@ pub struct AssingArrayStmt {
   id: Box<Identifier>,
   index: Box<dyn Expression>,
   expr: Box<dyn Expression>,
+  // Whether the element is stored with a `copy`.
+  copy: bool,
 }
 
 impl AssingArrayStmt {
   pub fn new(access: Box<AccessOp>, expr: Box<dyn Expression>) -> Result<AssingArrayStmt, String> {
-    if check_types(&access.typ(), &expr.typ()).is_none() {
-      return Err(String::from("Type Error"))
-    }
+    let copy = check_copy(access.typ(), expr.typ());
+    Type::expect_mixable(copy || check_types(access.typ(), expr.typ()).is_some(), access.typ(), expr.typ())?;
     Ok(AssingArrayStmt {
       id: access.array.clone(),
       index: access.index.box_clone(),
-      expr: expr.box_clone()
+      expr: convert(expr, access.typ())?,
+      copy: copy
     })
   }
 
@@ -106,14 +142,327 @@ impl AssingArrayStmt {
 }
 
 impl Statement for AssingArrayStmt {
-  fn generate(&mut self, b: &mut String, begin: i64, after: i64) -> Result<(), String> {
-    let idx = self.index.reduce(b)?;
-    let expr = self.expr.reduce(b)?;
-    emit(b, format!("{} [ {} ] = {}", self.id, idx, expr).as_str());
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String> {
+    let idx = self.index.reduce(cx)?;
+    if self.copy {
+      let src = self.expr.generate(cx)?;
+      let dst = Place::Index(Operand::Var(*self.id.clone()), operand_of(idx.as_ref())?);
+      return emit_copy(cx, dst, src.as_ref())
+    }
+    let expr = self.expr.reduce(cx)?;
+    let (index, src) = (operand_of(idx.as_ref())?, operand_of(expr.as_ref())?);
+    cx.emit(Instr::IndexStore { array: Operand::Var(*self.id.clone()), index: index, src: src });
+    Ok(())
+  }
+}
+
+pub struct DerefAssignStmt {
+  ptr: Box<dyn Expression>,
+  expr: Box<dyn Expression>,
+  // Whether the pointee is stored with a `copy`.
+  copy: bool,
+}
+
+impl DerefAssignStmt {
+  pub fn new(ptr: Box<dyn Expression>, expr: Box<dyn Expression>) -> Result<DerefAssignStmt, String> {
+    let to = match ptr.typ().pointee() {
+      Some(to) => to,
+      None => return Err(String::from("Type Error"))
+    };
+    let copy = check_copy(to, expr.typ());
+    Type::expect_mixable(copy || check_types(to, expr.typ()).is_some(), to, expr.typ())?;
+    let expr = convert(expr, to)?;
+    Ok(DerefAssignStmt { ptr: ptr, expr: expr, copy: copy })
+  }
+
+  pub fn new_box(ptr: Box<dyn Expression>, expr: Box<dyn Expression>) -> Result<Box<DerefAssignStmt>, String> {
+    let das = DerefAssignStmt::new(ptr, expr)?;
+    Ok(Box::new(das))
+  }
+}
+
+impl Statement for DerefAssignStmt {
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String> {
+    let ptr = self.ptr.reduce(cx)?;
+    if self.copy {
+      let src = self.expr.generate(cx)?;
+      return emit_copy(cx, Place::Deref(operand_of(ptr.as_ref())?), src.as_ref())
+    }
+    let expr = self.expr.reduce(cx)?;
+    cx.emit(Instr::Store { ptr: operand_of(ptr.as_ref())?, src: operand_of(expr.as_ref())? });
+    Ok(())
+  }
+}
+
+/// Sets up the descriptor of a runtime sized array when entering the block
+/// declaring it: its lengths are evaluated and stored after the address of
+/// the elements, which are then allocated in the frame of the function,
+///
+/// ```text
+/// a [ 8 ] = n
+/// t1 = n * 4
+/// a [ 0 ] = alloca t1
+/// ```
+///
+/// and released when it returns.
+pub struct DynamicArrayStmt {
+  array: Box<Identifier>,
+  lengths: Vec<Box<dyn Expression>>,
+}
+
+impl DynamicArrayStmt {
+  pub fn new(array: Box<Identifier>, lengths: Vec<Box<dyn Expression>>) -> Result<DynamicArrayStmt, String> {
+    if lengths.iter().any(|len| !len.typ().is_integral()) {
+      return Err(format!("Array size of {} should be integral", array))
+    }
+    let lengths = lengths.into_iter().map(|len| convert(len, Type::integer())).collect::<Result<_, _>>()?;
+    Ok(DynamicArrayStmt { array: array, lengths: lengths })
+  }
+
+  pub fn new_box(array: Box<Identifier>, lengths: Vec<Box<dyn Expression>>) -> Result<Box<DynamicArrayStmt>, String> {
+    let das = DynamicArrayStmt::new(array, lengths)?;
+    Ok(Box::new(das))
+  }
+}
+
+impl Statement for DynamicArrayStmt {
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String> {
+    let width = self.array.typ().element().width() as i64;
+    let mut size: Box<dyn Expression> = Box::new(Constant::integer(width));
+    for (i, len) in self.lengths.iter().enumerate() {
+      let len = len.reduce(cx)?;
+      let offset = 8 + Type::integer().width() * i as u32;
+      let field = Operand::Const(Constant::integer(offset as i64));
+      cx.emit(Instr::IndexStore { array: Operand::Var(*self.array.clone()), index: field, src: operand_of(len.as_ref())? });
+      size = ArithmeticOp::new_box(Token::Tok(b'*'), len, size)?;
+    }
+    let size = size.reduce(cx)?;
+    let data = Place::Index(Operand::Var(*self.array.clone()), Operand::Const(Constant::integer(0)));
+    cx.emit(Instr::Alloca { dst: data, size: operand_of(size.as_ref())? });
+    Ok(())
+  }
+}
+
+/// `target op= expr`, where the location of the target is only computed once.
+pub struct CompoundAssignStmt {
+  target: Box<dyn Expression>,
+  op: Token,
+  expr: Box<dyn Expression>,
+}
+
+impl CompoundAssignStmt {
+  pub fn new(target: Box<dyn Expression>, op: Token, expr: Box<dyn Expression>) -> Result<CompoundAssignStmt, String> {
+    if !is_lvalue(target.as_ref()) {
+      return Err(format!("Cannot assign to {}", target))
+    }
+    let result = ArithmeticOp::new(op.clone(), target.box_clone(), expr.box_clone())?;
+    if check_types(target.typ(), result.typ()).is_none() {
+      return Err(String::from("Type Error"))
+    }
+    Ok(CompoundAssignStmt { target: target, op: op, expr: expr })
+  }
+
+  pub fn new_box(target: Box<dyn Expression>, op: Token, expr: Box<dyn Expression>) -> Result<Box<CompoundAssignStmt>, String> {
+    let cas = CompoundAssignStmt::new(target, op, expr)?;
+    Ok(Box::new(cas))
+  }
+}
+
+impl Statement for CompoundAssignStmt {
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String> {
+    let target = self.target.generate(cx)?;
+    let expr = self.expr.reduce(cx)?;
+    let current = target.reduce(cx)?;
+    let result = convert(ArithmeticOp::new_box(self.op.clone(), current, expr)?, self.target.typ())?;
+    let value = if target.op().match_tag(Tag::ID) {
+      result.generate(cx)?
+    } else {
+      result.reduce(cx)?
+    };
+    emit_store(cx, target.as_ref(), value.as_ref())
+  }
+}
+
+pub struct CallStmt {
+  call: Box<CallOp>,
+}
+
+impl CallStmt {
+  pub fn new(call: Box<CallOp>) -> CallStmt {
+    CallStmt { call: call }
+  }
+
+  pub fn new_box(call: Box<CallOp>) -> Box<CallStmt> {
+    Box::new(CallStmt::new(call))
+  }
+}
+
+impl Statement for CallStmt {
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String> {
+    self.call.generate(cx)?;
+    cx.emit(self.call.call(None));
     Ok(())
   }
 }
 
+/// Name of the runtime routine handling values of `typ`, such as `print_int`
+/// or `read_unsigned_long`. Enumerations go through their integer value.
+fn runtime_routine(prefix: &str, typ: &Type) -> Option<String> {
+  let typ = if typ.is_enum() { Type::integer() } else { typ.resolve() };
+  if !typ.is_numeric() && typ != Type::boolean() {
+    return None
+  }
+  Some(format!("{}_{}", prefix, typ.to_string().replace(' ', "_")))
+}
+
+/// `print(e1, e2, ...)` writes the values of scalar expressions on one line.
+///
+/// The runtime provides, for every scalar type `T` (with spaces in its name
+/// replaced by underscores), a routine `print_T` taking the value as its only
+/// parameter and writing it, separated by a space from any value written
+/// before it on the same line. `print_line` takes no parameters and ends the
+/// current line. Each value is thus lowered to
+///
+/// ```text
+/// param x
+/// call print_int, 1
+/// ```
+///
+/// followed by a final `call print_line, 0`.
+pub struct PrintStmt {
+  args: Vec<(String, Box<dyn Expression>)>,
+}
+
+impl PrintStmt {
+  pub fn new(args: Vec<Box<dyn Expression>>) -> Result<PrintStmt, String> {
+    let mut routines = Vec::new();
+    for arg in args {
+      match runtime_routine("print", arg.typ()) {
+        Some(routine) => routines.push((routine, arg)),
+        None => return Err(format!("Cannot print a value of type {}", arg.typ()))
+      }
+    }
+    Ok(PrintStmt { args: routines })
+  }
+
+  pub fn new_box(args: Vec<Box<dyn Expression>>) -> Result<Box<PrintStmt>, String> {
+    let ps = PrintStmt::new(args)?;
+    Ok(Box::new(ps))
+  }
+}
+
+impl Statement for PrintStmt {
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String> {
+    for (routine, arg) in self.args.iter() {
+      // Generating first turns boolean expressions into a temporary.
+      let value = arg.generate(cx)?.reduce(cx)?;
+      cx.emit(Instr::Param(operand_of(value.as_ref())?));
+      cx.emit(Instr::Call { dst: None, name: routine.clone(), args: 1 });
+    }
+    cx.emit(Instr::Call { dst: None, name: String::from("print_line"), args: 0 });
+    Ok(())
+  }
+}
+
+/// `read(x)` stores in `x` the next value of its type from the input.
+///
+/// For every scalar type `T` the runtime provides a routine `read_T` taking no
+/// parameters and returning the value read, so `read(x)` on an `int` becomes
+/// `x = call read_int, 0`.
+pub struct ReadStmt {
+  target: Box<dyn Expression>,
+  routine: String,
+}
+
+impl ReadStmt {
+  pub fn new(target: Box<dyn Expression>) -> Result<ReadStmt, String> {
+    if !is_lvalue(target.as_ref()) {
+      return Err(format!("Cannot assign to {}", target))
+    }
+    let routine = match runtime_routine("read", target.typ()) {
+      Some(routine) => routine,
+      None => return Err(format!("Cannot read a value of type {}", target.typ()))
+    };
+    Ok(ReadStmt { target: target, routine: routine })
+  }
+
+  pub fn new_box(target: Box<dyn Expression>) -> Result<Box<ReadStmt>, String> {
+    let rs = ReadStmt::new(target)?;
+    Ok(Box::new(rs))
+  }
+}
+
+impl Statement for ReadStmt {
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String> {
+    let target = self.target.generate(cx)?;
+    let call = |dst| Instr::Call { dst: Some(dst), name: self.routine.clone(), args: 0 };
+    if target.op().match_tag(Tag::ID) {
+      cx.emit(call(operand_of(target.as_ref())?));
+      return Ok(())
+    }
+    let tmp = cx.new_temp(self.target.typ());
+    cx.emit(call(Operand::Temp(tmp.clone())));
+    emit_store(cx, target.as_ref(), &tmp)
+  }
+}
+
+/// `delete p` gives the storage `p` points to back to the heap with
+/// `free p`.
+pub struct DeleteStmt {
+  ptr: Box<dyn Expression>,
+}
+
+impl DeleteStmt {
+  pub fn new(ptr: Box<dyn Expression>) -> Result<DeleteStmt, String> {
+    if ptr.typ().pointee().is_none() {
+      return Err(format!("Cannot delete a value of type {}", ptr.typ()))
+    }
+    Ok(DeleteStmt { ptr: ptr })
+  }
+
+  pub fn new_box(ptr: Box<dyn Expression>) -> Result<Box<DeleteStmt>, String> {
+    let ds = DeleteStmt::new(ptr)?;
+    Ok(Box::new(ds))
+  }
+}
+
+impl Statement for DeleteStmt {
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String> {
+    let ptr = self.ptr.reduce(cx)?;
+    cx.emit(Instr::Free(operand_of(ptr.as_ref())?));
+    Ok(())
+  }
+}
+
+pub struct ReturnStmt {
+  expr: Box<dyn Expression>,
+}
+
+impl ReturnStmt {
+  pub fn new(expr: Box<dyn Expression>, ret: &Type) -> Result<ReturnStmt, String> {
+    Type::expect_mixable(check_types(ret, expr.typ()).is_some(), ret, expr.typ())?;
+    let expr = convert(expr, ret)?;
+    Ok(ReturnStmt { expr: expr })
+  }
+
+  pub fn new_box(expr: Box<dyn Expression>, ret: &Type) -> Result<Box<ReturnStmt>, String> {
+    let rs = ReturnStmt::new(expr, ret)?;
+    Ok(Box::new(rs))
+  }
+}
+
+impl Statement for ReturnStmt {
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String> {
+    let expr = self.expr.reduce(cx)?;
+    cx.emit(Instr::Return(Some(operand_of(expr.as_ref())?)));
+    Ok(())
+  }
+
+  fn returns(&self) -> bool {
+    true
+  }
+}
+
 pub struct StmtSeq {
   head: Box<dyn Statement>,
   tail: Box<dyn Statement>,
@@ -130,23 +479,28 @@ impl StmtSeq {
 }
 
 impl Statement for StmtSeq {
-  fn generate(&mut self, b: &mut String, begin: i64, after: i64) -> Result<(), String> {
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String> {
     if self.head.is_null() {
-      return self.tail.generate(b, begin, after);
+      return self.tail.generate(cx, begin, after);
     }
     if self.tail.is_null() {
-      return self.head.generate(b, begin, after);
+      return self.head.generate(cx, begin, after);
     }
-    let label = new_label();
-    self.head.generate(b, begin, label)?;
-    emit_label(b, label);
-    self.tail.generate(b, label, after)
+    let label = cx.new_label();
+    self.head.generate(cx, begin, label)?;
+    cx.emit_label(label);
+    self.tail.generate(cx, label, after)
   }
 
   fn after(&mut self, label: i64) {
     self.head.after(label);
     self.tail.after(label);
   }
+
+  // A statement after a return could still be reached with a goto.
+  fn returns(&self) -> bool {
+    self.tail.returns() || self.head.returns() && self.tail.is_null()
+  }
 }
 
 pub struct IfStmt {
@@ -169,11 +523,11 @@ impl IfStmt {
 }
 
 impl Statement for IfStmt {
-  fn generate(&mut self, b: &mut String, begin: i64, after: i64) -> Result<(), String> {
-    let label = new_label();
-    self.cond.jumps(b, 0, after)?;
-    emit_label(b, label);
-    self.body.generate(b, label, after)
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String> {
+    let label = cx.new_label();
+    self.cond.jumps(cx, 0, after)?;
+    cx.emit_label(label);
+    self.body.generate(cx, label, after)
   }
 
   fn after(&mut self, label: i64) {
@@ -202,21 +556,55 @@ impl ElseStmt {
 }
 
 impl Statement for ElseStmt {
-  fn generate(&mut self, b: &mut String, begin: i64, after: i64) -> Result<(), String> {
-    let label_if = new_label();
-    let label_else = new_label();
-    self.cond.jumps(b, 0, label_else)?;
-    emit_label(b, label_if);
-    self.true_stmt.generate(b, label_if, after)?;
-    emit(b, format!("goto L{}", after).as_str());
-    emit_label(b, label_else);
-    self.false_stmt.generate(b, label_else, after)
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String> {
+    let label_if = cx.new_label();
+    let label_else = cx.new_label();
+    self.cond.jumps(cx, 0, label_else)?;
+    cx.emit_label(label_if);
+    self.true_stmt.generate(cx, label_if, after)?;
+    cx.emit(Instr::Goto(after));
+    cx.emit_label(label_else);
+    self.false_stmt.generate(cx, label_else, after)
   }
 
   fn after(&mut self, label: i64) {
     self.true_stmt.after(label);
     self.false_stmt.after(label);
   }
+
+  fn returns(&self) -> bool {
+    self.true_stmt.returns() && self.false_stmt.returns()
+  }
+}
+
+/// `assert(cond)` checks `cond` at run time. When it does not hold, control
+/// reaches a `trap n` instruction, which stops the program reporting a failed
+/// assertion at source line `n`.
+pub struct AssertStmt {
+  cond: Box<dyn Expression>,
+  line: u32,
+}
+
+impl AssertStmt {
+  pub fn new(cond: Box<dyn Expression>, line: u32) -> Result<AssertStmt, String> {
+    if cond.typ() != Type::boolean() {
+      return Err(String::from("Assert condition should be of bool type"))
+    }
+    Ok(AssertStmt { cond: cond, line: line })
+  }
+
+  pub fn new_box(cond: Box<dyn Expression>, line: u32) -> Result<Box<AssertStmt>, String> {
+    let st = AssertStmt::new(cond, line)?;
+    Ok(Box::new(st))
+  }
+}
+
+impl Statement for AssertStmt {
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String> {
+    self.cond.jumps(cx, after, 0)?;
+    cx.emit(Instr::Trap { array: None, line: self.line });
+    Ok(())
+  }
 }
 
 pub struct WhileStmt {
@@ -239,13 +627,13 @@ impl WhileStmt {
 }
 
 impl Statement for WhileStmt {
-  fn generate(&mut self, b: &mut String, begin: i64, after: i64) -> Result<(), String> {
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String> {
     self.after(after);
-    self.cond.jumps(b, 0, after)?;
-    let label = new_label();
-    emit_label(b, label);
-    self.body.generate(b, label, begin)?;
-    emit(b, format!("goto L{}", begin).as_str());
+    self.cond.jumps(cx, 0, after)?;
+    let label = cx.new_label();
+    cx.emit_label(label);
+    self.body.generate(cx, label, begin)?;
+    cx.emit(Instr::Goto(begin));
     Ok(())
   }
 
@@ -274,12 +662,12 @@ impl DoStmt {
 }
 
 impl Statement for DoStmt {
-  fn generate(&mut self, b: &mut String, begin: i64, after: i64) -> Result<(), String> {
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String> {
     self.after(after);
-    let label = new_label();
-    self.body.generate(b, begin, label)?;
-    emit_label(b, label);
-    self.cond.jumps(b, begin, 0)
+    let label = cx.new_label();
+    self.body.generate(cx, begin, label)?;
+    cx.emit_label(label);
+    self.cond.jumps(cx, begin, 0)
   }
 
   fn after(&mut self, label: i64) {
@@ -287,6 +675,128 @@ impl Statement for DoStmt {
   }
 }
 
+// Switches with at least this many cases whose values cover at least half of
+// the range between the smallest and the largest one are lowered to a jump
+// table, everything else becomes a chain of comparisons.
+const TABLE_MIN_CASES: usize = 4;
+
+/// `switch (expr) { case K: ... default: ... }`.
+///
+/// Cases do not fall through: control leaves the switch at the end of every
+/// body, as well as on `break`. Several labels may share a body by stacking
+/// them (`case 1: case 2: ...`).
+pub struct SwitchStmt {
+  expr: Box<dyn Expression>,
+  cases: Vec<(i64, usize)>,
+  default: Option<usize>,
+  bodies: Vec<Box<dyn Statement>>,
+}
+
+impl SwitchStmt {
+  pub fn new(expr: Box<dyn Expression>) -> Result<SwitchStmt, String> {
+    if !expr.typ().is_integral() {
+      return Err(String::from("Switch expression should be of integral type"))
+    }
+    Ok(SwitchStmt { expr: expr, cases: Vec::new(), default: None, bodies: Vec::new() })
+  }
+
+  /// Adds a body reached through `values` and, when `default` is set, also
+  /// through the default label.
+  pub fn add(&mut self, values: &[i64], default: bool, body: Box<dyn Statement>) -> Result<(), String> {
+    let idx = self.bodies.len();
+    for value in values {
+      if self.cases.iter().any(|(v, _)| v == value) {
+        return match self.expr.typ().enumerator(*value) {
+          Some(name) => Err(format!("Duplicate case {}", name)),
+          None => Err(format!("Duplicate case {}", value))
+        }
+      }
+      self.cases.push((*value, idx));
+    }
+    if default {
+      if self.default.is_some() {
+        return Err(String::from("Duplicate default case"))
+      }
+      self.default = Some(idx);
+    }
+    self.bodies.push(body);
+    Ok(())
+  }
+
+  fn use_table(&self) -> bool {
+    if self.cases.len() < TABLE_MIN_CASES {
+      return false
+    }
+    let min = self.cases.iter().map(|(v, _)| *v).min().unwrap_or(0);
+    let max = self.cases.iter().map(|(v, _)| *v).max().unwrap_or(0);
+    match max.checked_sub(min).and_then(|d| d.checked_add(1)) {
+      Some(span) => span as usize <= 2 * self.cases.len(),
+      None => false
+    }
+  }
+}
+
+impl Statement for SwitchStmt {
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String> {
+    self.after(after);
+    let value = self.expr.reduce(cx)?;
+    let operand = operand_of(value.as_ref())?;
+    let labels: Vec<i64> = self.bodies.iter().map(|_| cx.new_label()).collect();
+    let default = match self.default {
+      Some(idx) => labels[idx],
+      None => after
+    };
+
+    if self.use_table() {
+      let min = self.cases.iter().map(|(v, _)| *v).min().unwrap_or(0);
+      let max = self.cases.iter().map(|(v, _)| *v).max().unwrap_or(0);
+      let (low, high) = (Operand::Const(Constant::integer(min)), Operand::Const(Constant::integer(max)));
+      cx.emit(Instr::IfGoto { cond: Cond::Relation(Token::Tok(b'<'), operand.clone(), low.clone()), label: default });
+      cx.emit(Instr::IfGoto { cond: Cond::Relation(Token::Tok(b'>'), operand.clone(), high), label: default });
+      let mut index = operand.clone();
+      if min != 0 {
+        let tmp = Operand::Temp(cx.new_temp(value.typ()));
+        cx.emit(Instr::BinOp { dst: tmp.clone(), op: Token::Tok(b'-'), left: operand, right: low });
+        index = tmp;
+      }
+      let table: Vec<i64> = (min..=max).map(|v| {
+        match self.cases.iter().find(|(cv, _)| *cv == v) {
+          Some((_, idx)) => labels[*idx],
+          None => default
+        }
+      }).collect();
+      cx.emit(Instr::JumpTable { labels: table, index: index });
+    } else {
+      for (v, idx) in &self.cases {
+        let case = Operand::Const(Constant::integer(*v));
+        cx.emit(Instr::IfGoto { cond: Cond::Relation(Token::Equality, operand.clone(), case), label: labels[*idx] });
+      }
+      cx.emit(Instr::Goto(default));
+    }
+
+    let last = self.bodies.len();
+    for (i, body) in self.bodies.iter_mut().enumerate() {
+      cx.emit_label(labels[i]);
+      body.generate(cx, labels[i], after)?;
+      // A body ending in a break or a return already jumps away.
+      let jumps = match cx.code().last() {
+        Some(Instr::Goto(_)) | Some(Instr::Return(_)) => true,
+        _ => false
+      };
+      if i + 1 != last && !jumps {
+        cx.emit(Instr::Goto(after));
+      }
+    }
+    Ok(())
+  }
+
+  fn after(&mut self, label: i64) {
+    for body in self.bodies.iter_mut() {
+      body.after(label);
+    }
+  }
+}
+
 pub struct BreakStmt {
   enc_after: i64,
 }
@@ -302,11 +812,11 @@ impl BreakStmt {
 }
 
 impl Statement for BreakStmt {
-  fn generate(&mut self, b: &mut String, begin: i64, after: i64) -> Result<(), String> {
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String> {
     if self.enc_after == 0 {
       return Err(String::from("Unenclosed break"));
     }
-    emit(b, format!("goto L{}", self.enc_after).as_str());
+    cx.emit(Instr::Goto(self.enc_after));
     Ok(())
   }
 
@@ -315,10 +825,85 @@ impl Statement for BreakStmt {
   }
 }
 
+/// Label of the generated code standing for a label of the source. It is
+/// numbered when the first statement using it is generated.
+#[derive(Clone, Default)]
+pub struct UserLabel {
+  number: Rc<Cell<i64>>,
+}
+
+impl UserLabel {
+  pub fn new() -> UserLabel {
+    UserLabel::default()
+  }
+
+  fn number(&self, cx: &mut CodegenContext) -> i64 {
+    if self.number.get() == 0 {
+      self.number.set(cx.new_label());
+    }
+    self.number.get()
+  }
+}
+
+/// `name: stmt`, a statement that can be jumped to with `goto name`.
+pub struct LabeledStmt {
+  label: UserLabel,
+  stmt: Box<dyn Statement>,
+}
+
+impl LabeledStmt {
+  pub fn new(label: UserLabel, stmt: Box<dyn Statement>) -> LabeledStmt {
+    LabeledStmt { label: label, stmt: stmt }
+  }
+
+  pub fn new_box(label: UserLabel, stmt: Box<dyn Statement>) -> Box<LabeledStmt> {
+    Box::new(LabeledStmt::new(label, stmt))
+  }
+}
+
+impl Statement for LabeledStmt {
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String> {
+    let label = self.label.number(cx);
+    cx.emit_label(label);
+    self.stmt.generate(cx, label, after)
+  }
+
+  fn after(&mut self, label: i64) {
+    self.stmt.after(label);
+  }
+
+  fn returns(&self) -> bool {
+    self.stmt.returns()
+  }
+}
+
+pub struct GotoStmt {
+  label: UserLabel,
+}
+
+impl GotoStmt {
+  pub fn new(label: UserLabel) -> GotoStmt {
+    GotoStmt { label: label }
+  }
+
+  pub fn new_box(label: UserLabel) -> Box<GotoStmt> {
+    Box::new(GotoStmt::new(label))
+  }
+}
+
+impl Statement for GotoStmt {
+  fn generate(&mut self, cx: &mut CodegenContext, begin: i64, after: i64) -> Result<(), String> {
+    let label = self.label.number(cx);
+    cx.emit(Instr::Goto(label));
+    Ok(())
+  }
+}
+
 #[cfg(test)]
 mod test {
-use crate::{reset_labels, new_label};
-use crate::expression::{Temp, Constant};
+use crate::CodegenContext;
+use crate::ir::print;
+use crate::expression::Constant;
 use lexer::tokens::{Tag, Token};
 
 use super::*;
@@ -408,13 +993,12 @@ fn statement_tests() {
   ];
 
   for mut tc in tests {
-    reset_labels();
-    Temp::reset_counter();
-
-    let begin = new_label();
-    let after = new_label();
+    let mut cx = CodegenContext::new();
+    let begin = cx.new_label();
+    let after = cx.new_label();
+    tc.0.generate(&mut cx, begin, after).expect("Generate error");
     let mut b = String::new();
-    tc.0.generate(&mut b, begin, after).expect("Generate error");
+    print(cx.code(), &mut b);
     assert_eq!(tc.1, b);
   }
 }
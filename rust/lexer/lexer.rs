@@ -25,35 +25,55 @@ impl<T: std::io::Read> Lexer<T> {
     lexer.words.insert(String::from("while"), Token::Word(String::from("while"), Tag::WHILE));
     lexer.words.insert(String::from("do"), Token::Word(String::from("do"), Tag::DO));
     lexer.words.insert(String::from("break"), Token::Word(String::from("break"), Tag::BREAK));
+    lexer.words.insert(String::from("goto"), Token::Word(String::from("goto"), Tag::GOTO));
+    lexer.words.insert(String::from("record"), Token::Word(String::from("record"), Tag::RECORD));
+    lexer.words.insert(String::from("enum"), Token::Word(String::from("enum"), Tag::ENUM));
+    lexer.words.insert(String::from("type"), Token::Word(String::from("type"), Tag::TYPE));
+    lexer.words.insert(String::from("return"), Token::Word(String::from("return"), Tag::RETURN));
+    lexer.words.insert(String::from("assert"), Token::Word(String::from("assert"), Tag::ASSERT));
+    lexer.words.insert(String::from("import"), Token::Word(String::from("import"), Tag::IMPORT));
+    lexer.words.insert(String::from("export"), Token::Word(String::from("export"), Tag::EXPORT));
+    lexer.words.insert(String::from("print"), Token::Word(String::from("print"), Tag::PRINT));
+    lexer.words.insert(String::from("read"), Token::Word(String::from("read"), Tag::READ));
+    lexer.words.insert(String::from("len"), Token::Word(String::from("len"), Tag::LEN));
+    lexer.words.insert(String::from("new"), Token::Word(String::from("new"), Tag::NEW));
+    lexer.words.insert(String::from("delete"), Token::Word(String::from("delete"), Tag::DELETE));
+    lexer.words.insert(String::from("const"), Token::Word(String::from("const"), Tag::CONST));
+    lexer.words.insert(String::from("switch"), Token::Word(String::from("switch"), Tag::SWITCH));
+    lexer.words.insert(String::from("case"), Token::Word(String::from("case"), Tag::CASE));
+    lexer.words.insert(String::from("default"), Token::Word(String::from("default"), Tag::DEFAULT));
     lexer.words.insert(String::from("true"), Token::true_token().clone());
     lexer.words.insert(String::from("false"), Token::false_token().clone());
+    lexer.words.insert(String::from("null"), Token::null_token().clone());
     lexer.words.insert(String::from("int"), Token::integer().clone());
     lexer.words.insert(String::from("float"), Token::float().clone());
     lexer.words.insert(String::from("char"), Token::ch().clone());
+    lexer.words.insert(String::from("long"), Token::long().clone());
+    lexer.words.insert(String::from("double"), Token::double().clone());
+    lexer.words.insert(String::from("byte"), Token::byte().clone());
+    lexer.words.insert(String::from("unsigned"), Token::Word(String::from("unsigned"), Tag::UNSIGNED));
     lexer.words.insert(String::from("bool"), Token::boolean().clone());
     lexer
   }
 
   pub fn scan(&mut self) -> Result<Token> {
-    if self.peek != b' ' {
-      let tok = Token::Tok(self.peek);
-      self.peek = b' ';
-      return Ok(tok)
-    }
-    if self.peek == b'\0' {
-      return Ok(Token::Eof);
-    }
+    // The character right after the previous token may still be in peek, so
+    // it goes through the same rules as a freshly read one.
     loop {
-      self.read()?;
       if self.peek == b' ' || self.peek == b'\t' || self.peek == b'\r' {
+        self.read()?;
         continue;
       }
       if self.peek == b'\n' {
         self.line += 1;
+        self.read()?;
         continue;
       }
       break;
     }
+    if self.peek == b'\0' {
+      return Ok(Token::Eof);
+    }
 
     match self.peek {
       b'&' => {
@@ -116,6 +136,23 @@ impl<T: std::io::Read> Lexer<T> {
           }
         }
       },
+      b'+' | b'-' | b'*' | b'/' => {
+        let op = self.peek;
+        self.read()?;
+        if self.peek == b'=' {
+          self.peek = b' ';
+          return Ok(Token::CompoundAssign(op))
+        }
+        if op == b'+' && self.peek == b'+' {
+          self.peek = b' ';
+          return Ok(Token::Inc)
+        }
+        if op == b'-' && self.peek == b'-' {
+          self.peek = b' ';
+          return Ok(Token::Dec)
+        }
+        return Ok(Token::Tok(op))
+      },
       _ => (),
     }
 
@@ -136,7 +173,7 @@ impl<T: std::io::Read> Lexer<T> {
       }
 
       if self.peek != b'.' {
-        return Ok(Token::Integer(val))
+        return self.suffixed(Token::Integer(val))
       }
 
       let mut x = val as f64;
@@ -155,7 +192,7 @@ impl<T: std::io::Read> Lexer<T> {
         x += (self.peek - b'0') as f64 / d;
         d *= 10.0;
       }
-      return Ok(Token::Real(x))
+      return self.suffixed(Token::Real(x))
     }
 
     if self.peek.is_ascii_alphabetic() {
@@ -188,6 +225,30 @@ impl<T: std::io::Read> Lexer<T> {
     Ok(tok)
   }
 
+  // A number may be followed by a suffix picking its type: `U` for unsigned,
+  // `L` for long, both for unsigned long, and `D` for double.
+  fn suffixed(&mut self, value: Token) -> Result<Token> {
+    let mut suffix = String::new();
+    while b"uUlLdD".contains(&self.peek) {
+      suffix.push(self.peek.to_ascii_uppercase() as char);
+      self.read()?;
+    }
+    let typ = match (&value, suffix.as_str()) {
+      (_, "") => return Ok(value),
+      (Token::Integer(_), "L") => Token::long().clone(),
+      (Token::Integer(_), "U") => Token::unsigned(Token::integer()).unwrap(),
+      (Token::Integer(_), "UL") | (Token::Integer(_), "LU") => Token::unsigned(Token::long()).unwrap(),
+      (Token::Integer(i), "D") => {
+        let value = Token::Real(*i as f64);
+        return Ok(Token::Literal(Box::new(value), Box::new(Token::double().clone())))
+      },
+      (Token::Real(_), "D") => Token::double().clone(),
+      _ => return Err(std::io::Error::new(
+        ErrorKind::InvalidData, format!("Invalid suffix {} for {}", suffix, value)))
+    };
+    Ok(Token::Literal(Box::new(value), Box::new(typ)))
+  }
+
   fn read_ch(&mut self, c: u8) -> Result<bool> {
     self.read()?;
     if self.peek != c {
@@ -232,6 +293,10 @@ fn simple(s: &str, w: u8) -> Token {
   Token::SimpleType(s.to_string(), w)
 }
 
+fn literal(value: Token, typ: Token) -> Token {
+  Token::Literal(Box::new(value), Box::new(typ))
+}
+
 fn int(i: i64) -> Token {
   Token::Integer(i)
 }
@@ -256,6 +321,18 @@ fn break_kwd() -> Token {
   Token::Word(String::from("break"), Tag::BREAK)
 }
 
+fn switch_kwd() -> Token {
+  Token::Word(String::from("switch"), Tag::SWITCH)
+}
+
+fn case_kwd() -> Token {
+  Token::Word(String::from("case"), Tag::CASE)
+}
+
+fn default_kwd() -> Token {
+  Token::Word(String::from("default"), Tag::DEFAULT)
+}
+
 fn true_kwd() -> Token {
   Token::Word(String::from("true"), Tag::TRUE)
 }
@@ -278,6 +355,31 @@ fn lexer_tests() {
     ("1982", vec![int(1982)]),
     ("1982.2891", vec![float(1982.2891)]),
     ("Iden7ifier23", vec![word("Iden7ifier23")]),
+    ("&x", vec![tok(b'&'), word("x")]),
+    ("!x", vec![tok(b'!'), word("x")]),
+    ("a==b", vec![word("a"), Token::Equality, word("b")]),
+    ("a\n;", vec![word("a"), tok(b';'), Token::Eof]),
+    ("int* p", vec![simple("int", 4u8), tok(b'*'), word("p")]),
+    ("i++ --j", vec![word("i"), Token::Inc, Token::Dec, word("j")]),
+    ("a+=1 b-=c*=d/=2", vec![
+      word("a"), Token::CompoundAssign(b'+'), int(1),
+      word("b"), Token::CompoundAssign(b'-'),
+      word("c"), Token::CompoundAssign(b'*'),
+      word("d"), Token::CompoundAssign(b'/'), int(2)]),
+    ("a+b-c", vec![word("a"), tok(b'+'), word("b"), tok(b'-'), word("c")]),
+    ("10L 3u 4UL 2.5D 7d", vec![
+      literal(int(10), simple("long", 8u8)),
+      literal(int(3), simple("unsigned int", 4u8)),
+      literal(int(4), simple("unsigned long", 8u8)),
+      literal(float(2.5), simple("double", 16u8)),
+      literal(float(7.0), simple("double", 16u8))]),
+    ("unsigned byte b", vec![
+      Token::Word(String::from("unsigned"), Tag::UNSIGNED), simple("byte", 1u8), word("b")]),
+    ("switch (x) { case 1: break; default: }",
+    vec![
+      switch_kwd(), tok(b'('), word("x"), tok(b')'), tok(b'{'),
+      case_kwd(), int(1), tok(b':'), break_kwd(), tok(b';'),
+      default_kwd(), tok(b':'), tok(b'}')]),
     ("{
         int i; int j; float v; float[100] a;
         while (true) {
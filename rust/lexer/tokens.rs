@@ -24,6 +24,34 @@ pub enum Tag {
   TEMP,
   TRUE,
   WHILE,
+  CASE,
+  DEFAULT,
+  SWITCH,
+  RETURN,
+  RECORD,
+  ADDRESS,
+  DEREF,
+  NULL,
+  POINTER,
+  CAST,
+  COMPOUND,
+  DEC,
+  INC,
+  COND,
+  ASSIGN,
+  CONST,
+  ENUM,
+  TYPE,
+  UNSIGNED,
+  PRINT,
+  READ,
+  ASSERT,
+  EXPORT,
+  IMPORT,
+  LEN,
+  DELETE,
+  NEW,
+  GOTO,
   EOF = std::u32::MAX as isize
 }
 
@@ -49,8 +77,13 @@ pub enum Token {
   Ne,
   Le,
   Ge,
+  Inc,
+  Dec,
+  CompoundAssign(u8),
   Integer(i64),
   Real(f64),
+  /// A numeric literal whose suffix picks its type.
+  Literal(Box<Token>, Box<Token>),
   SimpleType(String, u8),
   Array(Box<Token>, u32),
   Eof
@@ -64,11 +97,33 @@ impl Token {
       "while" => Tag::WHILE,
       "do" => Tag::DO,
       "break" => Tag::BREAK,
+      "record" => Tag::RECORD,
+      "enum" => Tag::ENUM,
+      "type" => Tag::TYPE,
+      "return" => Tag::RETURN,
+      "assert" => Tag::ASSERT,
+      "import" => Tag::IMPORT,
+      "export" => Tag::EXPORT,
+      "print" => Tag::PRINT,
+      "read" => Tag::READ,
+      "new" => Tag::NEW,
+      "goto" => Tag::GOTO,
+      "delete" => Tag::DELETE,
+      "len" => Tag::LEN,
+      "switch" => Tag::SWITCH,
+      "const" => Tag::CONST,
+      "case" => Tag::CASE,
+      "default" => Tag::DEFAULT,
       "true" => Tag::TRUE,
       "false" => Tag::FALSE,
+      "null" => Tag::NULL,
+      "unsigned" => Tag::UNSIGNED,
       "int" => return Self::integer().clone(),
       "float" => return Self::float().clone(),
       "char" => return Self::ch().clone(),
+      "long" => return Self::long().clone(),
+      "double" => return Self::double().clone(),
+      "byte" => return Self::byte().clone(),
       "bool" => return Self::boolean().clone(),
       _ => Tag::ID
     };
@@ -97,6 +152,38 @@ impl Token {
     &*c
   }
 
+  pub fn long() -> &'static Token {
+    static l: Lazy<Token> = Lazy::new(|| {
+      Token::SimpleType(String::from("long"), 8u8)
+    });
+    &*l
+  }
+
+  pub fn double() -> &'static Token {
+    static d: Lazy<Token> = Lazy::new(|| {
+      Token::SimpleType(String::from("double"), 16u8)
+    });
+    &*d
+  }
+
+  pub fn byte() -> &'static Token {
+    static b: Lazy<Token> = Lazy::new(|| {
+      Token::SimpleType(String::from("byte"), 1u8)
+    });
+    &*b
+  }
+
+  /// Unsigned variant of an integer type, which keeps its width.
+  pub fn unsigned(tok: &Token) -> Option<Token> {
+    match tok {
+      Token::SimpleType(lex, w) => match lex.as_str() {
+        "byte" | "int" | "long" => Some(Token::SimpleType(format!("unsigned {}", lex), *w)),
+        _ => None
+      },
+      _ => None
+    }
+  }
+
   pub fn boolean() -> &'static Token {
     static b: Lazy<Token> = Lazy::new(|| {
       Token::SimpleType(String::from("bool"), 1u8)
@@ -118,6 +205,13 @@ impl Token {
     &*f
   }
 
+  pub fn null_token() -> &'static Token {
+    static n: Lazy<Token> = Lazy::new(|| {
+      Token::Word(String::from("null"), Tag::NULL)
+    });
+    &*n
+  }
+
   pub fn temp_word() -> &'static Token {
     static w: Lazy<Token> = Lazy::new(|| {
       Token::Word(String::from("t"), Tag::TEMP)
@@ -132,6 +226,48 @@ impl Token {
     &*a
   }
 
+  pub fn address_word() -> &'static Token {
+    static a: Lazy<Token> = Lazy::new(|| {
+      Token::Word(String::from("&"), Tag::ADDRESS)
+    });
+    &*a
+  }
+
+  pub fn new_word() -> &'static Token {
+    static n: Lazy<Token> = Lazy::new(|| {
+      Token::Word(String::from("new"), Tag::NEW)
+    });
+    &*n
+  }
+
+  pub fn deref_word() -> &'static Token {
+    static d: Lazy<Token> = Lazy::new(|| {
+      Token::Word(String::from("*"), Tag::DEREF)
+    });
+    &*d
+  }
+
+  pub fn assign_word() -> &'static Token {
+    static a: Lazy<Token> = Lazy::new(|| {
+      Token::Word(String::from("="), Tag::ASSIGN)
+    });
+    &*a
+  }
+
+  pub fn cast_word() -> &'static Token {
+    static c: Lazy<Token> = Lazy::new(|| {
+      Token::Word(String::from("cast"), Tag::CAST)
+    });
+    &*c
+  }
+
+  pub fn cond_word() -> &'static Token {
+    static c: Lazy<Token> = Lazy::new(|| {
+      Token::Word(String::from("?:"), Tag::COND)
+    });
+    &*c
+  }
+
   pub fn eq_word() -> &'static Token {
     static e: Lazy<Token> = Lazy::new(|| {
       Token::Word(String::from("=="), Tag::EQ)
@@ -170,8 +306,12 @@ impl Token {
       Token::Ne => Tag::NE as u32,
       Token::Le => Tag::LE as u32,
       Token::Ge => Tag::GE as u32,
+      Token::Inc => Tag::INC as u32,
+      Token::Dec => Tag::DEC as u32,
+      Token::CompoundAssign(_) => Tag::COMPOUND as u32,
       Token::Integer(_) => Tag::INTEGER as u32,
       Token::Real(_) => Tag::REAL as u32,
+      Token::Literal(value, _) => value.tag(),
       Token::SimpleType(_, _) => Tag::BASIC as u32,
       Token::Array(_, _) => Tag::INDEX as u32,
       Token::Eof => Tag::EOF as u32
@@ -194,8 +334,12 @@ impl fmt::Display for Token {
       Token::Ne => write!(f, "!="),
       Token::Le => write!(f, "<="),
       Token::Ge => write!(f, ">="),
+      Token::Inc => write!(f, "++"),
+      Token::Dec => write!(f, "--"),
+      Token::CompoundAssign(op) => write!(f, "{}=", *op as char),
       Token::Integer(i) => write!(f, "{}", i),
       Token::Real(r) => write!(f, "{}", r.to_string()),
+      Token::Literal(value, _) => write!(f, "{}", value),
       Token::SimpleType(lex, _) => write!(f, "{}", lex),
       Token::Array(typ, len) => write!(f, "[{}]{}", len, *typ),
       Token::Eof => write!(f, "\0")
@@ -210,6 +354,10 @@ impl PartialEq for Token {
         Token::Word(otext, otag) => text == otext && tag == otag,
         _ => false
       },
+      Token::CompoundAssign(op) => match other {
+        Token::CompoundAssign(oop) => op == oop,
+        _ => false
+      },
       Token::Integer(value) => match other {
         Token::Integer(ovalue) => value == ovalue,
         _ => false
@@ -218,6 +366,10 @@ impl PartialEq for Token {
         Token::Real(_) => self.to_string() == other.to_string(),
         _ => false
       },
+      Token::Literal(value, typ) => match other {
+        Token::Literal(ovalue, otyp) => value == ovalue && typ == otyp,
+        _ => false
+      },
       Token::SimpleType(text, width) => match other {
         Token::SimpleType(otext, owidth) => text == otext && width == owidth,
         _ => false
@@ -1,13 +1,36 @@
+use std::fs::File;
 use std::io::BufReader;
+use std::path::Path;
 
 use lexer::Lexer;
 use parser::Parser;
 
+// Compiles the program in the file given as argument, or read from stdin when
+// there is none. Imports are looked up next to the file, or in the current
+// directory for stdin. With `--check-bounds` array subscripts are checked at
+// run time, and with `--value-comparisons` booleans are computed without
+// jumps.
 fn main() {
-  let lexer = Lexer::new(BufReader::new(std::io::stdin()));
-  let mut parser = Parser::new(lexer).expect("Creating parser");
-
+  let (flags, files): (Vec<String>, Vec<String>) = std::env::args().skip(1).partition(|arg| arg.starts_with("--"));
+  let check_bounds = flags.iter().any(|flag| flag == "--check-bounds");
+  let compare_values = flags.iter().any(|flag| flag == "--value-comparisons");
   let mut str = String::new();
-  parser.program(&mut str).expect("Parsing program");
+  match files.first() {
+    Some(path) => {
+      let file = File::open(path).expect("Opening program");
+      let lexer = Lexer::new(BufReader::new(file));
+      let mut parser = Parser::for_file(lexer, Path::new(path)).expect("Creating parser");
+      parser.check_bounds(check_bounds);
+      parser.compare_values(compare_values);
+      parser.program(&mut str).expect("Parsing program");
+    },
+    None => {
+      let lexer = Lexer::new(BufReader::new(std::io::stdin()));
+      let mut parser = Parser::new(lexer).expect("Creating parser");
+      parser.check_bounds(check_bounds);
+      parser.compare_values(compare_values);
+      parser.program(&mut str).expect("Parsing program");
+    }
+  }
   println!("{}", str);
 }
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use ast::expression as expr;
+use ast::function as func;
+use lexer::tokens as toks;
+
+/// Declarations a module makes visible to the files importing it, found by
+/// their unqualified names.
+#[derive(Default)]
+pub struct Exports {
+  pub functions: HashMap<String, func::Signature>,
+  pub constants: HashMap<String, expr::Constant>,
+  pub types: HashMap<String, ast::Type>,
+  pub variables: HashMap<String, expr::Identifier>,
+}
+
+/// State shared by the parsers of all the files making up a program. Every
+/// module is parsed once, no matter how many files import it, and its code is
+/// kept to be generated after the code of the program. Global variables of
+/// every file are laid out in a single static area.
+#[derive(Default)]
+pub struct Loader {
+  loaded: HashMap<PathBuf, Rc<Exports>>,
+  loading: Vec<(PathBuf, String)>,
+  pub functions: Vec<func::Function>,
+  pub globals: Vec<expr::Identifier>,
+  statics: i64,
+}
+
+impl Loader {
+  pub fn new() -> Loader {
+    Loader::default()
+  }
+
+  pub fn get(&self, path: &Path) -> Option<Rc<Exports>> {
+    self.loaded.get(path).cloned()
+  }
+
+  /// Marks the module at `path` as being parsed, failing when it is already
+  /// being parsed further up the chain of imports.
+  pub fn enter(&mut self, path: &Path, name: &str) -> Result<(), String> {
+    if let Some(pos) = self.loading.iter().position(|(p, _)| p == path) {
+      let mut chain: Vec<&str> = self.loading[pos..].iter().map(|(_, n)| n.as_str()).collect();
+      chain.push(name);
+      return Err(format!("Import cycle {}", chain.join(" -> ")))
+    }
+    self.loading.push((path.to_path_buf(), name.to_string()));
+    Ok(())
+  }
+
+  pub fn leave(&mut self, exports: Rc<Exports>, functions: Vec<func::Function>) {
+    if let Some((path, _)) = self.loading.pop() {
+      self.loaded.insert(path, exports);
+    }
+    self.functions.extend(functions);
+  }
+
+  pub fn allocate(&mut self, name: toks::Token, typ: &ast::Type) -> expr::Identifier {
+    let id = expr::Identifier::global(name, typ, self.statics as i32);
+    self.statics += typ.width() as i64;
+    self.globals.push(id.clone());
+    id
+  }
+
+  /// Forgets the module being parsed after it failed.
+  pub fn abandon(&mut self) {
+    self.loading.pop();
+  }
+}
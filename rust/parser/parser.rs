@@ -1,23 +1,40 @@
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
 use std::convert::Into;
-use std::mem::swap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::mem::{swap, take};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use lexer::tokens as toks;
 use ast::expression as expr;
 use ast::statement as stmt;
+use ast::function as func;
 use expr::Expression;
 
+mod module;
+
+#[derive(Clone)]
+enum Symbol {
+  Variable(expr::Identifier),
+  Constant(expr::Constant),
+}
+
+/// Scoped symbol table. Names of types live in their own namespace, apart
+/// from variables and constants.
 pub struct Environment {
-  table: HashMap<String, expr::Identifier>,
+  table: HashMap<String, Symbol>,
+  types: HashMap<String, ast::Type>,
   previous: Box<Option<Environment>>,
 }
 
 impl Environment {
   fn empty() -> Box<Environment> {
-    Box::new(Environment { table: HashMap::new(), previous: Box::new(None) })
+    Box::new(Environment { table: HashMap::new(), types: HashMap::new(), previous: Box::new(None) })
   }
 
   fn new(prev: Box<Environment>) -> Box<Environment> {
-    Box::new(Environment { table: HashMap::new(), previous: Box::new(Some(*prev)) })
+    Box::new(Environment { table: HashMap::new(), types: HashMap::new(), previous: Box::new(Some(*prev)) })
   }
 
   fn pop(&mut self) -> Result<Box<Environment>, String> {
@@ -30,57 +47,377 @@ impl Environment {
   }
 
   fn put(&mut self, key: &str, value: expr::Identifier) {
-    self.table.insert(key.to_string(), value);
+    self.table.insert(key.to_string(), Symbol::Variable(value));
   }
 
-  fn get(&self, key: &str) -> Result<expr::Identifier, String> {
+  fn put_constant(&mut self, key: &str, value: expr::Constant) {
+    self.table.insert(key.to_string(), Symbol::Constant(value));
+  }
+
+  fn put_type(&mut self, key: &str, typ: ast::Type) {
+    self.types.insert(key.to_string(), typ);
+  }
+
+  fn get_type(&self, key: &str) -> Option<ast::Type> {
+    match self.types.get(key) {
+      Some(typ) => Some(typ.clone()),
+      _ => match self.previous.as_ref() {
+        Some(env) => env.get_type(key),
+        _ => None
+      }
+    }
+  }
+
+  fn lookup(&self, key: &str) -> Result<Symbol, String> {
     match self.table.get(key) {
       Some(value) => Ok(value.clone()),
       _ => match self.previous.as_ref() {
-        Some(env) => env.get(key),
+        Some(env) => env.lookup(key),
         _ => Err(format!("Undeclrared identifier {}", key))
       }
     }
   }
+
+  fn get(&self, key: &str) -> Result<expr::Identifier, String> {
+    match self.lookup(key)? {
+      Symbol::Variable(id) => Ok(id),
+      Symbol::Constant(_) => Err(format!("{} is not a variable", key))
+    }
+  }
 }
 
 pub struct Parser<T: std::io::Read> {
   lexer: lexer::Lexer<T>,
   lookahead: toks::Token,
+  ahead: VecDeque<toks::Token>,
   top: Box<Environment>,
   used: i64,
+  functions: HashMap<String, func::Signature>,
+  ret: Option<ast::Type>,
+  // Name of the module being parsed, which qualifies its function labels.
+  module: Option<String>,
+  dir: PathBuf,
+  loader: Rc<RefCell<module::Loader>>,
+  modules: HashMap<String, Rc<module::Exports>>,
+  exports: module::Exports,
+  // Lengths of the runtime sized dimensions met while parsing the type of a
+  // local variable, the only place where they are allowed.
+  sizes: Option<Vec<Box<dyn expr::Expression>>>,
+  bounds_checks: bool,
+  value_comparisons: bool,
+  // Labels of the function being parsed, by name, with the blocks enclosing
+  // their definitions, and the gotos to be checked against them once the
+  // whole function is known. Blocks are numbered in `declares`, which tells
+  // whether they declare variables, and `scopes` holds the ones enclosing the
+  // statement being parsed.
+  labels: HashMap<String, stmt::UserLabel>,
+  defined: HashMap<String, Vec<usize>>,
+  gotos: Vec<(String, Vec<usize>, u32)>,
+  scopes: Vec<usize>,
+  declares: Vec<bool>,
 }
 
 impl<T: std::io::Read> Parser<T> {
   pub fn new(lexer: lexer::Lexer<T>) -> Result<Parser<T>, String> {
+    let loader = Rc::new(RefCell::new(module::Loader::new()));
+    Parser::create(lexer, None, PathBuf::from("."), loader)
+  }
+
+  /// Parser for a program read from `path`. Its imports are looked up in the
+  /// directory containing it.
+  pub fn for_file(lexer: lexer::Lexer<T>, path: &Path) -> Result<Parser<T>, String> {
+    let path = fs::canonicalize(path).map_err(|err| format!("Cannot open {}: {}", path.display(), err))?;
+    let name = path.file_stem().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let loader = Rc::new(RefCell::new(module::Loader::new()));
+    loader.borrow_mut().enter(&path, name.as_str())?;
+    let dir = path.parent().map(|d| d.to_path_buf()).unwrap_or_default();
+    Parser::create(lexer, None, dir, loader)
+  }
+
+  fn create(lexer: lexer::Lexer<T>, module: Option<String>, dir: PathBuf,
+            loader: Rc<RefCell<module::Loader>>) -> Result<Parser<T>, String> {
     let mut res = Parser {
       lexer: lexer,
       lookahead: toks::Token::Eof,
+      ahead: VecDeque::new(),
       top: Environment::empty(),
       used: 0,
+      functions: HashMap::new(),
+      ret: None,
+      module: module,
+      dir: dir,
+      loader: loader,
+      modules: HashMap::new(),
+      exports: module::Exports::default(),
+      sizes: None,
+      bounds_checks: false,
+      value_comparisons: false,
+      labels: HashMap::new(),
+      defined: HashMap::new(),
+      gotos: Vec::new(),
+      scopes: Vec::new(),
+      declares: Vec::new(),
     };
     res.next()?;
     Ok(res)
   }
 
+  /// Makes every array subscript be checked at run time against the length
+  /// of its dimension, in this file and in the modules it imports.
+  pub fn check_bounds(&mut self, on: bool) {
+    self.bounds_checks = on;
+  }
+
+  /// Makes the generated code compute boolean values with set-on-compare
+  /// instructions rather than jumps.
+  pub fn compare_values(&mut self, on: bool) {
+    self.value_comparisons = on;
+  }
+
+  /// Compiles a program and writes its code in textual form.
   pub fn program(&mut self, s: &mut String) -> Result<(), String> {
+    let code = self.code()?;
+    ast::ir::print(&code, s);
+    Ok(())
+  }
+
+  /// A program is a translation unit whose top level declarations surround
+  /// its main block.
+  pub fn code(&mut self) -> Result<Vec<ast::ir::Instr>, String> {
+    self.imports()?;
+    let mut functions = self.items()?;
     let mut stm = self.block()?;
-    let begin = ast::new_label();
-    let after = ast::new_label();
-    ast::emit_label(s, begin);
-    stm.generate(s, begin, after)?;
-    ast::emit_label(s, after);
+    self.resolve_labels()?;
+    functions.extend(self.items()?);
+    if !self.lookahead.match_tag(toks::Tag::EOF) {
+      return Err(format!("Syntax error near line {}", self.lexer.line))
+    }
+
+    let mut cx = ast::CodegenContext::new();
+    cx.compare_values(self.value_comparisons);
+    for id in self.loader.borrow().globals.iter() {
+      cx.emit(ast::ir::Instr::Global { var: ast::ir::Operand::Var(id.clone()), size: id.typ().width() });
+    }
+    let begin = cx.new_label();
+    let after = cx.new_label();
+    cx.emit_label(begin);
+    stm.generate(&mut cx, begin, after)?;
+    cx.emit_label(after);
+    let mut imported = Vec::new();
+    swap(&mut self.loader.borrow_mut().functions, &mut imported);
+    if functions.is_empty() && imported.is_empty() {
+      return Ok(cx.into_code())
+    }
+    // Keep the main block from running into the code of the functions.
+    cx.emit(ast::ir::Instr::Halt);
+    for mut function in functions.into_iter().chain(imported) {
+      function.generate(&mut cx)?;
+    }
+    Ok(cx.into_code())
+  }
+
+  fn imports(&mut self) -> Result<(), String> {
+    while self.lookahead.match_tag(toks::Tag::IMPORT) {
+      self.import()?;
+    }
+    Ok(())
+  }
+
+  // Top level declarations, up to the main block of a program or the end of
+  // the file. Declarations marked with `export` are visible to the files
+  // importing the module.
+  fn items(&mut self) -> Result<Vec<func::Function>, String> {
+    let mut functions = Vec::new();
+    while !self.lookahead.match_tag(b'{') && !self.lookahead.match_tag(toks::Tag::EOF) {
+      let export = self.lookahead.match_tag(toks::Tag::EXPORT);
+      if export {
+        self.next()?;
+      }
+      if self.lookahead.match_tag(toks::Tag::CONST) {
+        let name = self.constant()?;
+        if export {
+          self.export_constant(name.as_str())?;
+        }
+      } else if self.lookahead.match_tag(toks::Tag::ENUM) {
+        let name = self.enumeration()?;
+        if export {
+          let typ = self.export_type(name.as_str())?;
+          if let ast::Type::Enum { name: _, enumerators } = typ {
+            for (enumerator, _) in enumerators {
+              self.export_constant(enumerator.as_str())?;
+            }
+          }
+        }
+      } else if self.lookahead.match_tag(toks::Tag::TYPE) {
+        let name = self.alias()?;
+        if export {
+          self.export_type(name.as_str())?;
+        }
+      } else {
+        let typ = self.typ()?;
+        let tok = self.lookahead.clone();
+        self.match_token(toks::Tag::ID)?;
+        if self.lookahead.match_tag(b'(') {
+          functions.push(self.function(typ, tok, export)?);
+        } else {
+          self.global(typ, tok, export)?;
+        }
+      }
+    }
+    Ok(functions)
+  }
+
+  // Global variables are laid out in the static area shared by all the files
+  // of the program rather than in a frame.
+  fn global(&mut self, typ: ast::Type, tok: toks::Token, export: bool) -> Result<(), String> {
+    self.match_token(b';')?;
+    let name = self.qualify(&tok);
+    let id = self.loader.borrow_mut().allocate(name, &typ);
+    self.top.put(tok.to_string().as_str(), id.clone());
+    if export {
+      self.exports.variables.insert(tok.to_string(), id);
+    }
+    Ok(())
+  }
+
+  // Name of a top level declaration in the generated code, prefixed by the
+  // module declaring it.
+  fn qualify(&self, tok: &toks::Token) -> toks::Token {
+    match &self.module {
+      Some(module) => toks::Token::Word(format!("{}.{}", module, tok), toks::Tag::ID),
+      None => tok.clone()
+    }
+  }
+
+  fn export_constant(&mut self, name: &str) -> Result<(), String> {
+    if let Symbol::Constant(value) = self.top.lookup(name)? {
+      self.exports.constants.insert(name.to_string(), value);
+    }
+    Ok(())
+  }
+
+  fn export_type(&mut self, name: &str) -> Result<ast::Type, String> {
+    let typ = match self.top.get_type(name) {
+      Some(typ) => typ,
+      None => return Err(format!("Unknown type {}", name))
+    };
+    self.exports.types.insert(name.to_string(), typ.clone());
+    Ok(typ)
+  }
+
+  // `import name;` makes the declarations exported by `name.dr`, found next to
+  // the importing file, available as `name.member`.
+  fn import(&mut self) -> Result<(), String> {
+    let line = self.lexer.line;
+    self.match_token(toks::Tag::IMPORT)?;
+    let tok = self.lookahead.clone();
+    self.match_token(toks::Tag::ID)?;
+    self.match_token(b';')?;
+
+    let name = tok.to_string();
+    let path = self.dir.join(format!("{}.dr", name));
+    let path = match fs::canonicalize(&path) {
+      Ok(path) => path,
+      Err(_) => return Err(format!("Cannot find module {} near line {}", name, line))
+    };
+    let cached = self.loader.borrow().get(&path);
+    let exports = match cached {
+      Some(exports) => exports,
+      None => self.load(&path, name.as_str(), line)?
+    };
+    self.modules.insert(name, exports);
     Ok(())
   }
 
+  fn load(&mut self, path: &Path, name: &str, line: u32) -> Result<Rc<module::Exports>, String> {
+    self.loader.borrow_mut().enter(path, name)
+      .map_err(|err| format!("{} near line {}", err, line))?;
+    match Parser::<File>::module(path, name, self.loader.clone(), self.bounds_checks) {
+      Ok((exports, functions)) => {
+        let exports = Rc::new(exports);
+        self.loader.borrow_mut().leave(exports.clone(), functions);
+        Ok(exports)
+      },
+      Err(err) => {
+        self.loader.borrow_mut().abandon();
+        Err(format!("In module {}: {}", name, err))
+      }
+    }
+  }
+
+  fn function(&mut self, ret: ast::Type, name: toks::Token, export: bool) -> Result<func::Function, String> {
+    if self.functions.contains_key(name.to_string().as_str()) {
+      return Err(format!("Function {} redefined near line {}", name, self.lexer.line))
+    }
+
+    let mut empty = Environment::empty();
+    swap(&mut self.top, &mut empty);
+    self.top = Environment::new(empty);
+    let saved_used = self.used;
+    self.used = 0;
+
+    let mut params = Vec::new();
+    self.match_token(b'(')?;
+    while !self.lookahead.match_tag(b')') {
+      if !params.is_empty() {
+        self.match_token(b',')?;
+      }
+      let typ = self.typ()?;
+      let tok = self.lookahead.clone();
+      self.match_token(toks::Tag::ID)?;
+      let id = expr::Identifier::new(tok, &typ, self.used as i32);
+      self.top.put(id.to_string().as_str(), id.clone());
+      self.used += typ.width() as i64;
+      params.push(id);
+    }
+    self.match_token(b')')?;
+
+    let types = params.iter().map(|p| p.typ().clone()).collect();
+    let label = self.qualify(&name);
+    let signature = func::Signature::new(label, types, &ret);
+    self.functions.insert(name.to_string(), signature.clone());
+    if export {
+      self.exports.functions.insert(name.to_string(), signature.clone());
+    }
+
+    self.ret = Some(ret);
+    let body = self.block()?;
+    self.ret = None;
+    if !body.returns() {
+      return Err(format!("Function {} can end without returning a value near line {}", name, self.lexer.line))
+    }
+    self.resolve_labels()?;
+
+    let frame = self.used;
+    self.used = saved_used;
+    self.top = self.top.pop()?;
+    Ok(func::Function::new(signature, params, body, frame))
+  }
+
   fn next(&mut self) -> Result<(), String> {
-    self.lookahead = match self.lexer.scan() {
-      Ok(tok) => tok,
-      Err(err) => return Err(format!("{} near line {}", err, self.lexer.line))
+    self.lookahead = match self.ahead.pop_front() {
+      Some(tok) => tok,
+      None => self.scan()?
     };
     Ok(())
   }
 
+  fn scan(&mut self) -> Result<toks::Token, String> {
+    match self.lexer.scan() {
+      Ok(tok) => Ok(tok),
+      Err(err) => Err(format!("{} near line {}", err, self.lexer.line))
+    }
+  }
+
+  // Token `n` positions past the lookahead.
+  fn peek(&mut self, n: usize) -> Result<&toks::Token, String> {
+    while self.ahead.len() < n {
+      let tok = self.scan()?;
+      self.ahead.push_back(tok);
+    }
+    Ok(&self.ahead[n - 1])
+  }
+
   fn match_token<U: Into<u32>>(&mut self, tag: U) -> Result<(), String> {
     if !self.lookahead.match_tag(tag) {
       return Err(format!("Syntax error near line {}", self.lexer.line))
@@ -95,51 +432,338 @@ impl<T: std::io::Read> Parser<T> {
     swap(&mut self.top, &mut empty);
     self.top = Environment::new(empty);
 
-    self.decls()?;
-    let stmts = self.stmts()?;
+    let used = self.used;
+    let setup = self.decls()?;
+    self.scopes.push(self.declares.len());
+    self.declares.push(self.used != used);
+    let mut stmts = self.stmts()?;
+    self.scopes.pop();
     self.match_token(b'}')?;
 
     self.top = self.top.pop()?;
+    for stm in setup.into_iter().rev() {
+      stmts = stmt::StmtSeq::new_box(stm, stmts);
+    }
     Ok(stmts)
   }
 
-  fn decls(&mut self) -> Result<(), String> {
-    while self.lookahead.match_tag(toks::Tag::BASIC) {
-      let typ = self.typ()?;
+  // Checks the gotos of a function, or of the main block, once all its labels
+  // are known. A goto cannot jump into a block declaring variables, as their
+  // setup would be skipped.
+  fn resolve_labels(&mut self) -> Result<(), String> {
+    let gotos = take(&mut self.gotos);
+    let defined = take(&mut self.defined);
+    let declares = take(&mut self.declares);
+    self.labels.clear();
+    for (name, scopes, line) in gotos {
+      let target = match defined.get(&name) {
+        Some(target) => target,
+        None => return Err(format!("Undefined label {} near line {}", name, line))
+      };
+      if target.iter().any(|scope| !scopes.contains(scope) && declares[*scope]) {
+        return Err(format!("Jump to label {} skips declarations near line {}", name, line))
+      }
+    }
+    Ok(())
+  }
+
+  // Declarations at the start of a block, returning the statements setting up
+  // its runtime sized arrays.
+  fn decls(&mut self) -> Result<Vec<Box<dyn stmt::Statement>>, String> {
+    let mut setup: Vec<Box<dyn stmt::Statement>> = Vec::new();
+    while self.lookahead.match_tag(toks::Tag::BASIC)
+        || self.lookahead.match_tag(toks::Tag::UNSIGNED)
+        || self.lookahead.match_tag(toks::Tag::RECORD)
+        || self.lookahead.match_tag(toks::Tag::CONST)
+        || self.lookahead.match_tag(toks::Tag::ENUM)
+        || self.lookahead.match_tag(toks::Tag::TYPE)
+        || self.type_name().is_some()
+        || self.qualified_type()?.is_some() {
+      if self.lookahead.match_tag(toks::Tag::CONST) {
+        self.constant()?;
+        continue;
+      }
+      if self.lookahead.match_tag(toks::Tag::ENUM) {
+        self.enumeration()?;
+        continue;
+      }
+      if self.lookahead.match_tag(toks::Tag::TYPE) {
+        self.alias()?;
+        continue;
+      }
+      self.sizes = Some(Vec::new());
+      let typ = self.typ();
+      let sizes = self.sizes.take().unwrap_or_default();
+      let typ = typ?;
       let tok = self.lookahead.clone();
       self.match_token(toks::Tag::ID)?;
       self.match_token(b';')?;
       let id = expr::Identifier::new(tok, &typ, self.used as i32);
+      if !sizes.is_empty() {
+        setup.push(stmt::DynamicArrayStmt::new_box(Box::new(id.clone()), sizes)?);
+      }
       self.top.put(id.to_string().as_str(), id);
       self.used += typ.width() as i64;
     }
-    Ok(())
+    Ok(setup)
+  }
+
+  fn constant(&mut self) -> Result<String, String> {
+    self.match_token(toks::Tag::CONST)?;
+    let typ = self.typ()?;
+    let tok = self.lookahead.clone();
+    self.match_token(toks::Tag::ID)?;
+    self.match_token(b'=')?;
+    let ex = self.conditional()?;
+    self.match_token(b';')?;
+
+    let value = match ex.fold().map_err(|err| format!("{} near line {}", err, self.lexer.line))? {
+      Some(value) => value,
+      None => return Err(format!("Initializer of {} is not a constant near line {}", tok, self.lexer.line))
+    };
+    if !value.fits(&typ) {
+      return Err(format!("Constant {} does not fit in {} near line {}", value, typ, self.lexer.line))
+    }
+    let cast = value.cast(&typ).filter(|_| stmt::check_types(&typ, value.typ()).is_some());
+    ast::Type::expect_mixable(cast.is_some(), &typ, value.typ())
+      .map_err(|err| format!("{} near line {}", err, self.lexer.line))?;
+    let value = cast.unwrap();
+    self.top.put_constant(tok.to_string().as_str(), value);
+    Ok(tok.to_string())
+  }
+
+  // Values are numbered from zero or from the last explicit initializer, and
+  // enumerators become constants of the enumeration type.
+  fn enumeration(&mut self) -> Result<String, String> {
+    self.match_token(toks::Tag::ENUM)?;
+    let tok = self.lookahead.clone();
+    self.match_token(toks::Tag::ID)?;
+    self.match_token(b'{')?;
+    let mut enumerators: Vec<(String, i64)> = Vec::new();
+    let mut value = 0;
+    while !self.lookahead.match_tag(b'}') {
+      if !enumerators.is_empty() {
+        self.match_token(b',')?;
+      }
+      let name = self.lookahead.to_string();
+      self.match_token(toks::Tag::ID)?;
+      if self.lookahead.match_tag(b'=') {
+        self.next()?;
+        value = self.integral_constant()?;
+      }
+      if enumerators.iter().any(|(ename, _)| *ename == name) {
+        return Err(format!("Duplicate enumerator {} near line {}", name, self.lexer.line))
+      }
+      // Enumerations are stored as an int.
+      if !expr::Constant::integer(value).fits(ast::Type::integer()) {
+        return Err(format!("Enumerator {} does not fit in int near line {}", name, self.lexer.line))
+      }
+      enumerators.push((name, value));
+      value = match value.checked_add(1) {
+        Some(next) => next,
+        None => return Err(format!("Overflow in constant expression near line {}", self.lexer.line))
+      };
+    }
+    self.match_token(b'}')?;
+    self.match_token(b';')?;
+
+    let typ = ast::Type::enumeration(tok.to_string().as_str(), enumerators.clone());
+    for (name, value) in enumerators {
+      let constant = expr::Constant::integer(value).cast(&typ).unwrap();
+      self.top.put_constant(name.as_str(), constant);
+    }
+    self.top.put_type(tok.to_string().as_str(), typ);
+    Ok(tok.to_string())
+  }
+
+  fn alias(&mut self) -> Result<String, String> {
+    self.match_token(toks::Tag::TYPE)?;
+    let tok = self.lookahead.clone();
+    self.match_token(toks::Tag::ID)?;
+    self.match_token(b'=')?;
+    let typ = self.typ()?;
+    self.match_token(b';')?;
+    let name = tok.to_string();
+    self.top.put_type(name.as_str(), ast::Type::alias(name.as_str(), typ));
+    Ok(name)
+  }
+
+  // The type named by the lookahead, if it is an identifier declared as one.
+  fn type_name(&self) -> Option<ast::Type> {
+    if !self.lookahead.match_tag(toks::Tag::ID) {
+      return None
+    }
+    self.top.get_type(self.lookahead.to_string().as_str())
+  }
+
+  // The module an identifier refers to, unless a variable or constant with
+  // the same name hides it.
+  fn module_named(&self, tok: &toks::Token) -> Option<Rc<module::Exports>> {
+    let name = tok.to_string();
+    if self.top.lookup(name.as_str()).is_ok() {
+      return None
+    }
+    self.modules.get(name.as_str()).cloned()
+  }
+
+  // Parses the `.member` following the name of a module.
+  fn member(&mut self) -> Result<String, String> {
+    self.match_token(b'.')?;
+    let tok = self.lookahead.clone();
+    self.match_token(toks::Tag::ID)?;
+    Ok(tok.to_string())
+  }
+
+  fn exported_function(&self, exports: &module::Exports, module: &toks::Token,
+                       member: &str) -> Result<func::Signature, String> {
+    match exports.functions.get(member) {
+      Some(sig) => Ok(sig.clone()),
+      None => Err(format!("{} is not exported by {} near line {}", member, module, self.lexer.line))
+    }
+  }
+
+  fn exported(&self, exports: &module::Exports, module: &toks::Token,
+              member: &str) -> Result<Symbol, String> {
+    if let Some(id) = exports.variables.get(member) {
+      return Ok(Symbol::Variable(id.clone()))
+    }
+    match exports.constants.get(member) {
+      Some(c) => Ok(Symbol::Constant(c.clone())),
+      None => Err(format!("{} is not exported by {} near line {}", member, module, self.lexer.line))
+    }
+  }
+
+  // A type exported by an imported module, named as `module.Type`.
+  fn qualified_type(&mut self) -> Result<Option<ast::Type>, String> {
+    if !self.lookahead.match_tag(toks::Tag::ID) {
+      return Ok(None)
+    }
+    let exports = match self.module_named(&self.lookahead) {
+      Some(exports) => exports,
+      None => return Ok(None)
+    };
+    if !self.peek(1)?.match_tag(b'.') {
+      return Ok(None)
+    }
+    let member = self.peek(2)?.to_string();
+    Ok(exports.types.get(member.as_str()).cloned())
+  }
+
+  // Evaluates an integral constant expression, as found in array dimensions
+  // and case labels.
+  fn integral_constant(&mut self) -> Result<i64, String> {
+    let ex = self.conditional()?;
+    self.integral_value(ex.as_ref())
+  }
+
+  fn integral_value(&self, ex: &dyn expr::Expression) -> Result<i64, String> {
+    let value = ex.fold().map_err(|err| format!("{} near line {}", err, self.lexer.line))?;
+    match value.map(|c| c.op().clone()) {
+      Some(toks::Token::Integer(val)) => Ok(val),
+      _ => Err(format!("Expected an integral constant near line {}", self.lexer.line))
+    }
   }
 
   fn typ(&mut self) -> Result<ast::Type, String> {
-    let typ = ast::Type::new(&self.lookahead)?;
-    self.match_token(toks::Tag::BASIC)?;
+    let typ = self.base_type()?;
     if !self.lookahead.match_tag(b'[') {
       return Ok(typ)
     }
     self.dims(typ)
   }
 
-  fn dims(&mut self, typ: ast::Type) -> Result<ast::Type, String> {
-    self.match_token(b'[')?;
-    let tok = self.lookahead.clone();
-    self.match_token(toks::Tag::INTEGER)?;
-    let size = match tok {
-      toks::Token::Integer(val) => val,
-      _ => return Err(format!("Syntax error near line {}", self.lexer.line))
+  // A type up to its array dimensions.
+  fn base_type(&mut self) -> Result<ast::Type, String> {
+    let typ = if self.lookahead.match_tag(toks::Tag::RECORD) {
+      self.record()?
+    } else if let Some(typ) = self.type_name() {
+      self.next()?;
+      typ
+    } else if let Some(typ) = self.qualified_type()? {
+      self.next()?;
+      self.member()?;
+      typ
+    } else if self.lookahead.match_tag(toks::Tag::UNSIGNED) {
+      self.next()?;
+      let tok = match toks::Token::unsigned(&self.lookahead) {
+        Some(tok) => tok,
+        None => return Err(format!("Invalid unsigned type {} near line {}", self.lookahead, self.lexer.line))
+      };
+      self.match_token(toks::Tag::BASIC)?;
+      ast::Type::new(&tok)?
+    } else {
+      let typ = ast::Type::new(&self.lookahead)?;
+      self.match_token(toks::Tag::BASIC)?;
+      typ
     };
-    self.match_token(b']')?;
+    let mut typ = typ;
+    while self.lookahead.match_tag(b'*') {
+      self.next()?;
+      typ = ast::Type::pointer(typ);
+    }
+    Ok(typ)
+  }
+
+  fn record(&mut self) -> Result<ast::Type, String> {
+    self.match_token(toks::Tag::RECORD)?;
+    self.match_token(b'{')?;
+    let mut fields: Vec<(String, ast::Type)> = Vec::new();
+    let sizes = self.sizes.take();
+    while !self.lookahead.match_tag(b'}') {
+      let typ = self.typ()?;
+      let tok = self.lookahead.clone();
+      self.match_token(toks::Tag::ID)?;
+      self.match_token(b';')?;
+      let name = tok.to_string();
+      if fields.iter().any(|(fname, _)| *fname == name) {
+        return Err(format!("Duplicate field {} near line {}", name, self.lexer.line))
+      }
+      fields.push((name, typ));
+    }
+    self.sizes = sizes;
+    self.match_token(b'}')?;
+    Ok(ast::Type::record(fields))
+  }
+
+  // An array has a runtime sized descriptor as soon as one of its lengths is
+  // not a constant.
+  fn dims(&mut self, typ: ast::Type) -> Result<ast::Type, String> {
+    let mut lengths = Vec::new();
+    while self.lookahead.match_tag(b'[') {
+      self.next()?;
+      let len = self.conditional()?;
+      // A length that fails to fold is an error rather than a runtime length.
+      len.fold().map_err(|err| format!("{} near line {}", err, self.lexer.line))?;
+      if let Ok(size) = self.integral_value(len.as_ref()) {
+        if size <= 0 {
+          return Err(format!("Array size should be positive near line {}", self.lexer.line))
+        }
+      }
+      self.match_token(b']')?;
+      lengths.push(len);
+    }
 
-    let mut of = typ.clone();
-    if self.lookahead.match_tag(b'[') {
-      of = self.dims(typ)?;
+    let sizes: Option<Vec<i64>> = lengths.iter().map(|len| self.integral_value(len.as_ref()).ok()).collect();
+    if let Some(sizes) = sizes {
+      let mut typ = typ;
+      for size in sizes.into_iter().rev() {
+        // Both the length and the width of the array have to fit in a u32.
+        let size = u32::try_from(size).ok().filter(|size| typ.width().checked_mul(*size).is_some());
+        typ = match size {
+          Some(size) => ast::Type::array(typ, size),
+          None => return Err(format!("Array size too large near line {}", self.lexer.line))
+        };
+      }
+      return Ok(typ)
     }
-    Ok(ast::Type::array(of, size as u32))
+    let line = self.lexer.line;
+    let runtime = match self.sizes.as_mut() {
+      Some(runtime) => runtime,
+      None => return Err(format!("Expected an integral constant near line {}", line))
+    };
+    let dims = lengths.len();
+    runtime.extend(lengths);
+    Ok((0..dims).fold(typ, |of, _| ast::Type::dynamic(of)))
   }
 
   fn stmts(&mut self) -> Result<Box<dyn stmt::Statement>, String> {
@@ -158,7 +782,20 @@ impl<T: std::io::Read> Parser<T> {
     const WHILE: u32 = toks::Tag::WHILE as u32;
     const DO: u32 = toks::Tag::DO as u32;
     const BREAK: u32 = toks::Tag::BREAK as u32;
+    const SWITCH: u32 = toks::Tag::SWITCH as u32;
+    const RETURN: u32 = toks::Tag::RETURN as u32;
+    const PRINT: u32 = toks::Tag::PRINT as u32;
+    const READ: u32 = toks::Tag::READ as u32;
+    const ASSERT: u32 = toks::Tag::ASSERT as u32;
+    const DELETE: u32 = toks::Tag::DELETE as u32;
+    const GOTO: u32 = toks::Tag::GOTO as u32;
+    const STAR: u32 = b'*' as u32;
+    const INC: u32 = toks::Tag::INC as u32;
+    const DEC: u32 = toks::Tag::DEC as u32;
 
+    if self.lookahead.match_tag(toks::Tag::ID) && self.peek(1)?.match_tag(b':') {
+      return self.labeled()
+    }
     match self.lookahead.tag() {
       SEMICOLON => {
         self.next()?;
@@ -167,7 +804,7 @@ impl<T: std::io::Read> Parser<T> {
       IF => {
         self.match_token(IF)?;
         self.match_token(b'(')?;
-        let ex = self.boolean()?;
+        let ex = self.assignment()?;
         self.match_token(b')')?;
         let body = self.stmt()?;
         if !self.lookahead.match_tag(toks::Tag::ELSE) {
@@ -183,7 +820,7 @@ impl<T: std::io::Read> Parser<T> {
         self.match_token(WHILE)?;
         self.match_token(b'(')?;
 
-        let ex = self.boolean()?;
+        let ex = self.assignment()?;
         if ex.typ() != ast::Type::boolean() {
           return Err(String::from("Expression in boolean condition is required for while loop."))
         }
@@ -199,7 +836,7 @@ impl<T: std::io::Read> Parser<T> {
 
         self.match_token(WHILE)?;
         self.match_token(b'(')?;
-        let ex = self.boolean()?;
+        let ex = self.assignment()?;
         if ex.typ() != ast::Type::boolean() {
           return Err(String::from("Expression in boolean condition is required for while loop."))
         }
@@ -214,33 +851,277 @@ impl<T: std::io::Read> Parser<T> {
         let brk = stmt::BreakStmt::new_box();
         Ok(brk)
       },
+      RETURN => {
+        self.match_token(RETURN)?;
+        let ret = match &self.ret {
+          Some(ret) => ret.clone(),
+          None => return Err(format!("Return outside of a function near line {}", self.lexer.line))
+        };
+        let ex = self.assignment()?;
+        self.match_token(b';')?;
+        let stm = stmt::ReturnStmt::new_box(ex, &ret)?;
+        Ok(stm)
+      },
+      PRINT => {
+        self.match_token(PRINT)?;
+        self.match_token(b'(')?;
+        let mut args = Vec::new();
+        while !self.lookahead.match_tag(b')') {
+          if !args.is_empty() {
+            self.match_token(b',')?;
+          }
+          args.push(self.assignment()?);
+        }
+        self.match_token(b')')?;
+        self.match_token(b';')?;
+        let stm = stmt::PrintStmt::new_box(args)?;
+        Ok(stm)
+      },
+      READ => {
+        self.match_token(READ)?;
+        self.match_token(b'(')?;
+        let target = self.unary()?;
+        self.match_token(b')')?;
+        self.match_token(b';')?;
+        let stm = stmt::ReadStmt::new_box(target)?;
+        Ok(stm)
+      },
+      ASSERT => {
+        self.match_token(ASSERT)?;
+        let line = self.lexer.line;
+        self.match_token(b'(')?;
+        let ex = self.assignment()?;
+        self.match_token(b')')?;
+        self.match_token(b';')?;
+        let stm = stmt::AssertStmt::new_box(ex, line)?;
+        Ok(stm)
+      },
+      GOTO => {
+        self.match_token(GOTO)?;
+        let line = self.lexer.line;
+        let name = self.lookahead.to_string();
+        self.match_token(toks::Tag::ID)?;
+        self.match_token(b';')?;
+        self.gotos.push((name.clone(), self.scopes.clone(), line));
+        Ok(stmt::GotoStmt::new_box(self.label(&name)))
+      },
+      DELETE => {
+        self.match_token(DELETE)?;
+        let ptr = self.unary()?;
+        self.match_token(b';')?;
+        let stm = stmt::DeleteStmt::new_box(ptr)?;
+        Ok(stm)
+      },
+      STAR => {
+        self.match_token(STAR)?;
+        let ptr = self.unary()?;
+        if !self.lookahead.match_tag(b'=') {
+          let target = expr::DerefOp::new_box(ptr)?;
+          return self.update(target)
+        }
+        self.match_token(b'=')?;
+        let ex = self.assignment()?;
+        self.match_token(b';')?;
+        let stm = stmt::DerefAssignStmt::new_box(ptr, ex)?;
+        Ok(stm)
+      },
+      INC | DEC => {
+        let tok = self.lookahead.clone();
+        self.next()?;
+        let target = self.unary()?;
+        let one = Parser::<T>::step(target.typ())?;
+        let op = if tok.match_tag(INC) { toks::Token::Tok(b'+') } else { toks::Token::Tok(b'-') };
+        self.match_token(b';')?;
+        let stm = stmt::CompoundAssignStmt::new_box(target, op, one)?;
+        Ok(stm)
+      },
+      SWITCH => self.switch(),
       OPEN_BR => self.block(),
       _ => self.assign()
     }
   }
 
-  fn assign(&mut self) -> Result<Box<dyn stmt::Statement>, String> {
-    let tok = self.lookahead.clone();
+  fn labeled(&mut self) -> Result<Box<dyn stmt::Statement>, String> {
+    let name = self.lookahead.to_string();
     self.match_token(toks::Tag::ID)?;
+    self.match_token(b':')?;
+    if self.defined.contains_key(&name) {
+      return Err(format!("Duplicate label {} near line {}", name, self.lexer.line))
+    }
+    self.defined.insert(name.clone(), self.scopes.clone());
+    let label = self.label(&name);
+    let body = self.stmt()?;
+    Ok(stmt::LabeledStmt::new_box(label, body))
+  }
 
-    let id = self.top.get(tok.to_string().as_str())?;
+  fn label(&mut self, name: &str) -> stmt::UserLabel {
+    self.labels.entry(name.to_string()).or_default().clone()
+  }
 
-    if self.lookahead.match_tag(b'=') {
-      self.next()?;
-      let expr = self.boolean()?;
-      let stm = stmt::AssignStmt::new_box(Box::new(id), expr)?;
-      self.match_token(b';')?;
-      return Ok(stm);
-    }
+  fn switch(&mut self) -> Result<Box<dyn stmt::Statement>, String> {
+    self.match_token(toks::Tag::SWITCH)?;
+    self.match_token(b'(')?;
+    let ex = self.assignment()?;
+    self.match_token(b')')?;
+    let typ = ex.typ().clone();
+    let mut switch = stmt::SwitchStmt::new(ex)?;
 
-    let access = self.offset(id)?;
-    self.match_token(b'=')?;
-    let expr = self.boolean()?;
+    self.match_token(b'{')?;
+    while !self.lookahead.match_tag(b'}') {
+      let mut values = Vec::new();
+      let mut default = false;
+      loop {
+        if self.lookahead.match_tag(toks::Tag::CASE) {
+          self.next()?;
+          let label = self.conditional()?;
+          ast::Type::expect_mixable(stmt::check_types(&typ, label.typ()).is_some(), &typ, label.typ())
+            .map_err(|err| format!("{} near line {}", err, self.lexer.line))?;
+          values.push(self.integral_value(label.as_ref())?);
+        } else if self.lookahead.match_tag(toks::Tag::DEFAULT) {
+          self.next()?;
+          default = true;
+        } else {
+          break
+        }
+        self.match_token(b':')?;
+      }
+      if values.is_empty() && !default {
+        return Err(format!("Syntax error near line {}", self.lexer.line))
+      }
+
+      let mut body: Vec<Box<dyn stmt::Statement>> = Vec::new();
+      while !self.lookahead.match_tag(toks::Tag::CASE)
+          && !self.lookahead.match_tag(toks::Tag::DEFAULT)
+          && !self.lookahead.match_tag(b'}') {
+        body.push(self.stmt()?);
+      }
+      let mut seq: Box<dyn stmt::Statement> = stmt::NullStmt::new_box();
+      while let Some(head) = body.pop() {
+        seq = stmt::StmtSeq::new_box(head, seq);
+      }
+      switch.add(&values, default, seq)
+        .map_err(|err| format!("{} near line {}", err, self.lexer.line))?;
+    }
+    self.match_token(b'}')?;
+    Ok(Box::new(switch))
+  }
+
+  fn assign(&mut self) -> Result<Box<dyn stmt::Statement>, String> {
+    let tok = self.lookahead.clone();
+    self.match_token(toks::Tag::ID)?;
+
+    if self.lookahead.match_tag(b'(') {
+      let call = self.call(tok)?;
+      self.match_token(b';')?;
+      return Ok(stmt::CallStmt::new_box(call));
+    }
+    let module = if self.lookahead.match_tag(b'.') { self.module_named(&tok) } else { None };
+    let id = match module {
+      Some(exports) => {
+        let member = self.member()?;
+        if self.lookahead.match_tag(b'(') {
+          let sig = self.exported_function(&exports, &tok, member.as_str())?;
+          let call = self.arguments(&sig)?;
+          self.match_token(b';')?;
+          return Ok(stmt::CallStmt::new_box(call));
+        }
+        match self.exported(&exports, &tok, member.as_str())? {
+          Symbol::Variable(id) => id,
+          Symbol::Constant(_) => return Err(format!("{}.{} is not a variable", tok, member))
+        }
+      },
+      None => self.top.get(tok.to_string().as_str())?
+    };
+
+    if self.lookahead.match_tag(b'=') {
+      self.next()?;
+      let expr = self.assignment()?;
+      let stm = stmt::AssignStmt::new_box(Box::new(id), expr)?;
+      self.match_token(b';')?;
+      return Ok(stm);
+    }
+    if !self.lookahead.match_tag(b'[') && !self.lookahead.match_tag(b'.') {
+      return self.update(Box::new(id))
+    }
+    if id.typ().dimensions() > 0 {
+      let ptr = self.element(id)?;
+      if !self.lookahead.match_tag(b'=') {
+        return self.update(expr::DerefOp::new_box(ptr)?)
+      }
+      self.match_token(b'=')?;
+      let expr = self.assignment()?;
+      let stm = stmt::DerefAssignStmt::new_box(ptr, expr)?;
+      self.match_token(b';')?;
+      return Ok(stm)
+    }
+
+    let access = self.offset(id)?;
+    if !self.lookahead.match_tag(b'=') {
+      return self.update(access)
+    }
+    self.match_token(b'=')?;
+    let expr = self.assignment()?;
     let stm = stmt::AssingArrayStmt::new_box(access, expr)?;
     self.match_token(b';')?;
     Ok(stm)
   }
 
+  // Compound assignments and increments used as statements.
+  fn update(&mut self, target: Box<dyn expr::Expression>) -> Result<Box<dyn stmt::Statement>, String> {
+    let tok = self.lookahead.clone();
+    let (op, ex) = match tok {
+      toks::Token::CompoundAssign(op) => {
+        self.next()?;
+        let mut ex = self.assignment()?;
+        if let Some(to) = target.typ().pointee() {
+          ex = Parser::<T>::scale(ex, to)?;
+        }
+        (toks::Token::Tok(op), ex)
+      },
+      toks::Token::Inc | toks::Token::Dec => {
+        self.next()?;
+        let op = if tok.match_tag(toks::Tag::INC) { toks::Token::Tok(b'+') } else { toks::Token::Tok(b'-') };
+        (op, Parser::<T>::step(target.typ())?)
+      },
+      _ => return Err(format!("Syntax error near line {}", self.lexer.line))
+    };
+    self.match_token(b';')?;
+    let stm = stmt::CompoundAssignStmt::new_box(target, op, ex)?;
+    Ok(stm)
+  }
+
+  fn step(typ: &ast::Type) -> Result<Box<dyn expr::Expression>, String> {
+    let one = Box::new(expr::Constant::integer(1));
+    match typ.pointee() {
+      Some(to) => Parser::<T>::scale(one, to),
+      None => Ok(one)
+    }
+  }
+
+  fn assignment(&mut self) -> Result<Box<dyn expr::Expression>, String> {
+    let ex = self.conditional()?;
+    if !self.lookahead.match_tag(b'=') {
+      return Ok(ex)
+    }
+    self.next()?;
+    let right = self.assignment()?;
+    let assign = expr::AssignOp::new_box(ex, right)?;
+    Ok(assign)
+  }
+
+  fn conditional(&mut self) -> Result<Box<dyn expr::Expression>, String> {
+    let ex = self.boolean()?;
+    if !self.lookahead.match_tag(b'?') {
+      return Ok(ex)
+    }
+    self.next()?;
+    let left = self.conditional()?;
+    self.match_token(b':')?;
+    let right = self.conditional()?;
+    let cond = expr::CondOp::new_box(ex, left, right)?;
+    Ok(cond)
+  }
+
   fn boolean(&mut self) -> Result<Box<dyn expr::Expression>, String> {
     let mut ex = self.join()?;
     while self.lookahead.match_tag(toks::Tag::OR) {
@@ -298,12 +1179,25 @@ impl<T: std::io::Read> Parser<T> {
     while self.lookahead.match_tag(b'+') || self.lookahead.match_tag(b'-') {
       let tok = self.lookahead.clone();
       self.next()?;
-      let right = self.term()?;
+      let mut right = self.term()?;
+      if let Some(to) = ex.typ().pointee() {
+        right = Parser::<T>::scale(right, to)?;
+      } else if let Some(to) = right.typ().pointee() {
+        ex = Parser::<T>::scale(ex, to)?;
+      }
       ex = expr::ArithmeticOp::new_box(tok, ex, right)?;
     }
     Ok(ex)
   }
 
+  // Pointer arithmetic moves in steps of the pointee, the same way offset
+  // scales array subscripts by the width of the element.
+  fn scale(ex: Box<dyn expr::Expression>, to: &ast::Type) -> Result<Box<dyn expr::Expression>, String> {
+    let width = Box::new(expr::Constant::integer(to.width() as i64));
+    let scaled = expr::ArithmeticOp::new_box(toks::Token::Tok(b'*'), ex, width)?;
+    Ok(scaled)
+  }
+
   fn term(&mut self) -> Result<Box<dyn expr::Expression>, String> {
     let mut ex = self.unary()?;
     while self.lookahead.match_tag(b'*') || self.lookahead.match_tag(b'/') {
@@ -318,7 +1212,13 @@ impl<T: std::io::Read> Parser<T> {
   fn unary(&mut self) -> Result<Box<dyn expr::Expression>, String> {
     const MINUS: u32 = b'-' as u32;
     const EXCL: u32 = b'!' as u32;
+    const AMP: u32 = b'&' as u32;
+    const STAR: u32 = b'*' as u32;
+    const INC: u32 = toks::Tag::INC as u32;
+    const DEC: u32 = toks::Tag::DEC as u32;
+    const NEW: u32 = toks::Tag::NEW as u32;
     match self.lookahead.tag() {
+      NEW => self.allocation(),
       MINUS => {
         self.next()?;
         let mut ex = self.unary()?;
@@ -332,22 +1232,77 @@ impl<T: std::io::Read> Parser<T> {
         ex = expr::NotLogicOp::new_box(tok, ex)?;
         Ok(ex)
       }
+      AMP => {
+        self.next()?;
+        let mut ex = self.factor()?;
+        ex = expr::AddressOp::new_box(ex)?;
+        Ok(ex)
+      }
+      STAR => {
+        self.next()?;
+        let mut ex = self.unary()?;
+        ex = expr::DerefOp::new_box(ex)?;
+        Ok(ex)
+      }
+      INC | DEC => {
+        let tok = self.lookahead.clone();
+        self.next()?;
+        let mut ex = self.unary()?;
+        ex = expr::IncDecOp::new_box(tok, ex, true)?;
+        Ok(ex)
+      }
       _ => self.factor()
     }
   }
 
+  // `new T`, or `new T[n]` where dimensions after `n` belong to the type of
+  // the values allocated.
+  fn allocation(&mut self) -> Result<Box<dyn expr::Expression>, String> {
+    self.match_token(toks::Tag::NEW)?;
+    let typ = self.base_type()?;
+    if !self.lookahead.match_tag(b'[') {
+      let ex = expr::NewOp::new_box(&typ, Box::new(expr::Constant::integer(1)))?;
+      return Ok(ex)
+    }
+    self.next()?;
+    let count = self.assignment()?;
+    if let Ok(size) = self.integral_value(count.as_ref()) {
+      if size <= 0 {
+        return Err(format!("Array size should be positive near line {}", self.lexer.line))
+      }
+    }
+    self.match_token(b']')?;
+    let of = if self.lookahead.match_tag(b'[') { self.dims(typ)? } else { typ };
+    let ex = expr::NewOp::new_box(&of, count)?;
+    Ok(ex)
+  }
+
   fn factor(&mut self) -> Result<Box<dyn expr::Expression>, String> {
     const OPAREN: u32 = b'(' as u32;
     const INTEGER: u32 = toks::Tag::INTEGER as u32;
     const REAL: u32 = toks::Tag::REAL as u32;
     const TRUE: u32 = toks::Tag::TRUE as u32;
     const FALSE: u32 = toks::Tag::FALSE as u32;
+    const NULL: u32 = toks::Tag::NULL as u32;
     const ID: u32 = toks::Tag::ID as u32;
+    const LEN: u32 = toks::Tag::LEN as u32;
 
     match self.lookahead.tag() {
+      LEN => self.len(),
       OPAREN => {
         self.next()?;
-        let ex = self.boolean()?;
+        if self.lookahead.match_tag(toks::Tag::BASIC)
+            || self.lookahead.match_tag(toks::Tag::UNSIGNED)
+            || self.lookahead.match_tag(toks::Tag::RECORD)
+            || self.type_name().is_some()
+            || self.qualified_type()?.is_some() {
+          let typ = self.typ()?;
+          self.match_token(b')')?;
+          let ex = self.unary()?;
+          let cast = expr::CastOp::new_box(&typ, ex)?;
+          return Ok(cast)
+        }
+        let ex = self.assignment()?;
         self.match_token(b')')?;
         Ok(ex)
       },
@@ -362,56 +1317,238 @@ impl<T: std::io::Read> Parser<T> {
         Ok(ex)
       },
       FALSE => {
-        let ex = expr::Constant::true_constant().box_clone();
+        let ex = expr::Constant::false_constant().box_clone();
+        self.next()?;
+        Ok(ex)
+      },
+      NULL => {
+        let ex = expr::Constant::null_constant().box_clone();
         self.next()?;
         Ok(ex)
       },
       ID => {
-        let id = self.top.get(format!("{}", self.lookahead).as_str())?;
+        let tok = self.lookahead.clone();
         self.next()?;
-        if self.lookahead.match_tag(b'[') {
-          let ex = self.offset(id)?;
+        if self.lookahead.match_tag(b'(') {
+          let ex = self.call(tok)?;
           return Ok(ex)
         }
-        Ok(Box::new(id))
+        let module = if self.lookahead.match_tag(b'.') { self.module_named(&tok) } else { None };
+        let symbol = match module {
+          Some(exports) => {
+            let member = self.member()?;
+            if self.lookahead.match_tag(b'(') {
+              let sig = self.exported_function(&exports, &tok, member.as_str())?;
+              let ex = self.arguments(&sig)?;
+              return Ok(ex)
+            }
+            self.exported(&exports, &tok, member.as_str())?
+          },
+          None => self.top.lookup(tok.to_string().as_str())?
+        };
+        let id = match symbol {
+          Symbol::Variable(id) => id,
+          Symbol::Constant(c) => return Ok(Box::new(c))
+        };
+        let mut ex: Box<dyn expr::Expression> = Box::new(id.clone());
+        if id.typ().dimensions() > 0 && self.lookahead.match_tag(b'[') {
+          ex = expr::DerefOp::new_box(self.element(id)?)?;
+        } else if self.lookahead.match_tag(b'[') || self.lookahead.match_tag(b'.') {
+          ex = self.offset(id)?;
+        }
+        if self.lookahead.match_tag(toks::Tag::INC) || self.lookahead.match_tag(toks::Tag::DEC) {
+          let tok = self.lookahead.clone();
+          self.next()?;
+          ex = expr::IncDecOp::new_box(tok, ex, false)?;
+        }
+        Ok(ex)
       },
       _ => Err(String::from("Syntax Error"))
     }
   }
 
-  fn offset(&mut self, id: expr::Identifier) -> Result<Box<expr::AccessOp>, String> {
-    let mut typ = id.typ().clone();
+  // `len(a)` is the length of an array, and `len(a, d)` the length of its
+  // dimension `d`, counting from 0. They are constants unless the dimension is
+  // runtime sized.
+  fn len(&mut self) -> Result<Box<dyn expr::Expression>, String> {
+    self.match_token(toks::Tag::LEN)?;
+    self.match_token(b'(')?;
+    let ex = self.assignment()?;
+    let mut dim = 0;
+    if self.lookahead.match_tag(b',') {
+      self.next()?;
+      dim = self.integral_constant()?;
+    }
+    self.match_token(b')')?;
 
-    self.match_token(b'[')?;
-    let index = self.boolean()?;
-    self.match_token(b']')?;
+    let dims = ex.typ().dimensions() as i64;
+    if 0 <= dim && dim < dims {
+      return match ex.operand() {
+        Some(ast::ir::Operand::Var(id)) => Ok(Parser::<T>::length(&id, dim as u32)),
+        _ => Err(format!("len expects an array near line {}", self.lexer.line))
+      }
+    }
+    let mut typ = ex.typ().element().clone();
+    for _ in dims..dim.max(0) {
+      typ = match typ.resolve() {
+        ast::Type::Array { of, length: _ } => *of.clone(),
+        _ => break
+      };
+    }
+    match typ.resolve() {
+      ast::Type::Array { of: _, length } if dim >= 0 => Ok(Box::new(expr::Constant::integer(*length as i64))),
+      _ if dim == 0 => Err(format!("len expects an array near line {}", self.lexer.line)),
+      _ => Err(format!("{} has no dimension {} near line {}", ex, dim, self.lexer.line))
+    }
+  }
 
-    match typ {
-      ast::Type::Array{of, length: _} => typ = *of.clone(),
-      _ => return Err(String::from("String error"))
+  fn call(&mut self, name: toks::Token) -> Result<Box<expr::CallOp>, String> {
+    let sig = match self.functions.get(name.to_string().as_str()) {
+      Some(sig) => sig.clone(),
+      None => return Err(format!("Undeclared function {} near line {}", name, self.lexer.line))
     };
+    self.arguments(&sig)
+  }
 
-    let width = Box::new(expr::Constant::integer(typ.width() as i64));
-    let t1 = expr::ArithmeticOp::new_box(toks::Token::Tok(b'*'), index, width)?;
+  fn arguments(&mut self, sig: &func::Signature) -> Result<Box<expr::CallOp>, String> {
+    self.match_token(b'(')?;
+    let mut args = Vec::new();
+    while !self.lookahead.match_tag(b')') {
+      if !args.is_empty() {
+        self.match_token(b',')?;
+      }
+      args.push(self.assignment()?);
+    }
+    self.match_token(b')')?;
+    expr::CallOp::new_box(sig, args)
+  }
 
-    let mut loc = t1;
-    while self.lookahead.match_tag(b'[') {
+  fn offset(&mut self, id: expr::Identifier) -> Result<Box<expr::AccessOp>, String> {
+    let (typ, loc) = self.selectors(id.typ().clone(), None, &id)?;
+    match loc {
+      Some(l) => Ok(expr::AccessOp::new_box(Box::new(id), l, &typ)),
+      None => Err(format!("Syntax error near line {}", self.lexer.line))
+    }
+  }
+
+  // Address of an element of a runtime sized array, or of a part of it, which
+  // takes a subscript for each dimension. Its offset is computed from the
+  // lengths stored in the descriptor.
+  fn element(&mut self, id: expr::Identifier) -> Result<Box<dyn expr::Expression>, String> {
+    let dims = id.typ().dimensions();
+    let mut loc: Option<Box<dyn expr::Expression>> = None;
+    for dim in 0..dims {
+      if !self.lookahead.match_tag(b'[') {
+        return Err(format!("Array {} needs {} subscripts near line {}", id, dims, self.lexer.line))
+      }
+      let line = self.lexer.line;
       self.match_token(b'[')?;
-      let index = self.boolean()?;
+      let index = self.assignment()?;
       self.match_token(b']')?;
+      let index = self.checked(index, Parser::<T>::length(&id, dim), &id, line);
+      loc = Some(match loc {
+        Some(l) => {
+          let len = Parser::<T>::length(&id, dim);
+          let row = expr::ArithmeticOp::new_box(toks::Token::Tok(b'*'), l, len)?;
+          expr::ArithmeticOp::new_box(toks::Token::Tok(b'+'), row, index)?
+        },
+        None => index
+      });
+    }
+    let of = id.typ().element().clone();
+    let width = Box::new(expr::Constant::integer(of.width() as i64));
+    let loc = expr::ArithmeticOp::new_box(toks::Token::Tok(b'*'), loc.unwrap(), width)?;
+    let (typ, loc) = self.selectors(of, Some(loc), &id)?;
+
+    let data = expr::AccessOp::new_box(Box::new(id), Box::new(expr::Constant::integer(0)), &ast::Type::pointer(typ));
+    let ptr = expr::ArithmeticOp::new_box(toks::Token::Tok(b'+'), data, loc.unwrap())?;
+    Ok(ptr)
+  }
+
+  // Subscript checked against the length of its dimension when bounds checks
+  // are on, unless it is a constant known to be in range. A failed check
+  // reports the `line` of the subscript.
+  fn checked(&self, index: Box<dyn expr::Expression>, length: Box<dyn expr::Expression>,
+             id: &expr::Identifier, line: u32) -> Box<dyn expr::Expression> {
+    if !self.bounds_checks {
+      return index
+    }
+    if let (Ok(i), Ok(len)) = (self.integral_value(index.as_ref()), self.integral_value(length.as_ref())) {
+      if 0 <= i && i < len {
+        return index
+      }
+    }
+    expr::BoundsCheckOp::new_box(index, length, id.to_string().as_str(), line)
+  }
+
+  // Length of a dimension of a runtime sized array, read from its descriptor.
+  fn length(id: &expr::Identifier, dim: u32) -> Box<dyn expr::Expression> {
+    let offset = 8 + ast::Type::integer().width() * dim;
+    let offset = Box::new(expr::Constant::integer(offset as i64));
+    expr::AccessOp::new_box(Box::new(id.clone()), offset, ast::Type::integer())
+  }
+
+  // Subscripts and field selections adding to the offset `loc` of a value of
+  // type `typ`, returning the type and offset of the selected part.
+  fn selectors(&mut self, typ: ast::Type, loc: Option<Box<dyn expr::Expression>>, id: &expr::Identifier)
+      -> Result<(ast::Type, Option<Box<dyn expr::Expression>>), String> {
+    let mut typ = typ;
+    let mut loc = loc;
 
-      match typ {
-        ast::Type::Array{of, length: _} => typ = *of.clone(),
-        _ => return Err(String::from("String error"))
+    while self.lookahead.match_tag(b'[') || self.lookahead.match_tag(b'.') {
+      let t1: Box<dyn expr::Expression> = if self.lookahead.match_tag(b'[') {
+        let line = self.lexer.line;
+        self.match_token(b'[')?;
+        let index = self.assignment()?;
+        self.match_token(b']')?;
+
+        let index = match typ.resolve().clone() {
+          ast::Type::Array{of, length} => {
+            typ = *of;
+            self.checked(index, Box::new(expr::Constant::integer(length as i64)), id, line)
+          },
+          _ => return Err(String::from("String error"))
+        };
+        let width = Box::new(expr::Constant::integer(typ.width() as i64));
+        expr::ArithmeticOp::new_box(toks::Token::Tok(b'*'), index, width)?
+      } else {
+        self.match_token(b'.')?;
+        let tok = self.lookahead.clone();
+        self.match_token(toks::Tag::ID)?;
+
+        let (ftyp, offset) = match typ.field(tok.to_string().as_str()) {
+          Some(field) => field,
+          None => return Err(format!("Unknown field {} near line {}", tok, self.lexer.line))
+        };
+        typ = ftyp;
+        Box::new(expr::Constant::integer(offset as i64))
       };
-      let width = Box::new(expr::Constant::integer(typ.width() as i64));
-      let t1 = expr::ArithmeticOp::new_box(toks::Token::Tok(b'*'), index, width)?;
 
-      let t2 = expr::ArithmeticOp::new_box(toks::Token::Tok(b'+'), loc, t1)?;
-      loc = t2;
+      loc = match loc {
+        Some(l) => Some(expr::ArithmeticOp::new_box(toks::Token::Tok(b'+'), l, t1)?),
+        None => Some(t1)
+      };
     }
+    Ok((typ, loc))
+  }
+}
 
-    Ok(expr::AccessOp::new_box(Box::new(id), loc, &typ))
+impl Parser<File> {
+  // Parses the module at `path`, returning what it exports and the code of
+  // its functions.
+  fn module(path: &Path, name: &str, loader: Rc<RefCell<module::Loader>>, bounds_checks: bool)
+      -> Result<(module::Exports, Vec<func::Function>), String> {
+    let file = File::open(path).map_err(|err| format!("Cannot open {}: {}", path.display(), err))?;
+    let lexer = lexer::Lexer::new(BufReader::new(file));
+    let dir = path.parent().map(|d| d.to_path_buf()).unwrap_or_default();
+    let mut parser = Parser::create(lexer, Some(name.to_string()), dir, loader)?;
+    parser.check_bounds(bounds_checks);
+    parser.imports()?;
+    let functions = parser.items()?;
+    if !parser.lookahead.match_tag(toks::Tag::EOF) {
+      return Err(format!("Syntax error near line {}", parser.lexer.line))
+    }
+    Ok((parser.exports, functions))
   }
 }
 
@@ -422,6 +1559,16 @@ use super::*;
 use std::io::BufReader;
 use stringreader::StringReader;
 
+// Code for the program `src`, compiled by a parser set up by `configure`.
+fn compile(src: &str, configure: fn(&mut Parser<StringReader>)) -> String {
+  let lexer = lexer::Lexer::new(BufReader::new(StringReader::new(src)));
+  let mut parser = Parser::new(lexer).expect("Creating parser");
+  configure(&mut parser);
+  let mut str = String::new();
+  parser.program(&mut str).expect("Parsing program");
+  str
+}
+
 #[test]
 fn parser_tests() {
   let tests: Vec<(&str, &str)> = vec![
@@ -430,6 +1577,25 @@ fn parser_tests() {
     ("{int i;float f;bool[100] b;}", "L1:L2:"),
     ("{int i; i = 10;}", "L1:\ti = 10\nL2:"),
     ("{int i; i = i + 10;}", "L1:\ti = i + 10\nL2:"),
+    ("{bool b; b = false;}", "L1:\tb = false\nL2:"),
+    (
+      r#"bool same(bool b) { return b; }
+      { bool b; int i; b = same(i < 2); b = same(same(b)); }"#,
+      r#"L1:	iffalse i < 2 goto L4
+	t1 = true
+	goto L5
+L4:	t1 = false
+L5:	param t1
+	b = call same, 1
+L3:	param b
+	t2 = call same, 1
+	param t2
+	b = call same, 1
+L2:	halt
+same:L6:	return b
+L7:	return
+"#,
+    ),
     (
       "{int i;int[20] arr; i = 10; arr[i] = 10;}",
       r#"L1:	i = 10
@@ -528,21 +1694,1007 @@ L11:	t9 = j * 8
 	goto L1
 L2:"#,
     ),
+    (
+      "{int i; int x; switch (i) { case 1: x = 10; case 2: case 3: x = 20; break; default: x = 0; } }",
+      r#"L1:	if i == 1 goto L3
+	if i == 2 goto L4
+	if i == 3 goto L4
+	goto L5
+L3:	x = 10
+	goto L2
+L4:	x = 20
+L6:	goto L2
+L5:	x = 0
+L2:"#,
+    ),
+    (
+      "{int i; int x; switch (i + 1) { case 1: x = 10; case 2: x = 20; case 4: x = 40; case 5: x = 50; } }",
+      r#"L1:	t1 = i + 1
+	if t1 < 1 goto L2
+	if t1 > 5 goto L2
+	t2 = t1 - 1
+	goto [ L3, L4, L2, L5, L6 ] [ t2 ]
+L3:	x = 10
+	goto L2
+L4:	x = 20
+	goto L2
+L5:	x = 40
+	goto L2
+L6:	x = 50
+L2:"#,
+    ),
+    (
+      r#"{ long x; int i;
+        switch (x) { case 9000000000000000000L: i = 1; case -9000000000000000000L: i = 2; case 0L: i = 3; case 1L: i = 4; } }"#,
+      r#"L1:	if x == 9000000000000000000 goto L3
+	if x == -9000000000000000000 goto L4
+	if x == 0 goto L5
+	if x == 1 goto L6
+	goto L2
+L3:	i = 1
+	goto L2
+L4:	i = 2
+	goto L2
+L5:	i = 3
+	goto L2
+L6:	i = 4
+L2:"#,
+    ),
+    (
+      r#"int sq(int x) { return x * x; }
+      bool pos(int x) { if (x > 0) return true; return false; }
+      { int y; y = sq(3) + 1; if (pos(y)) y = 0; sq(2); }"#,
+      r#"L1:	param 3
+	t1 = call sq, 1
+	y = t1 + 1
+L3:	param y
+	t2 = call pos, 1
+	iffalse t2 goto L4
+L5:	y = 0
+L4:	param 2
+	call sq, 1
+L2:	halt
+sq:L6:	t3 = x * x
+	return t3
+L7:	return
+pos:L8:	iffalse x > 0 goto L10
+L11:	return true
+L10:	return false
+L9:	return
+"#,
+    ),
+    (
+      r#"float avg(int a, float b) { float s; s = a + b; return s / 2; }
+      { float z; z = avg(avg(1, 2.5), 1.5); }"#,
+      r#"L1:	param 1
+	param 2.5
+	t1 = call avg, 2
+	t2 = (int) t1
+	param t2
+	param 1.5
+	z = call avg, 2
+L2:	halt
+avg:L3:	t3 = (float) a
+	s = t3 + b
+L5:	t4 = s / 2
+	return t4
+L4:	return
+"#,
+    ),
+    (
+      r#"{ int i; record { int x; float[4] v; } p; record { char c; int y; } [10] a;
+        p.x = 1; p.v[i] = 2.5; a[i].y = p.x; i = a[2].y + p.x; }"#,
+      r#"L1:	p [ 0 ] = 1
+L3:	t1 = i * 8
+	t2 = 4 + t1
+	p [ t2 ] = 2.5
+L4:	t3 = i * 5
+	t4 = t3 + 1
+	t5 = p [ 0 ]
+	a [ t4 ] = t5
+L5:	t6 = 2 * 5
+	t7 = t6 + 1
+	t8 = a [ t7 ]
+	t9 = p [ 0 ]
+	i = t8 + t9
+L2:"#,
+    ),
+    (
+      r#"{ int i; int* p; float[10] a; float* q;
+        p = &i; *p = 3; i = *p + 1; q = &a[i]; q = q + 2; *(q - 1) = 1.5;
+        p = null; if (p != null) i = *p; }"#,
+      r#"L1:	p = &i
+L3:	*p = 3
+L4:	t1 = *p
+	i = t1 + 1
+L5:	t2 = i * 8
+	q = &a [ t2 ]
+L6:	t3 = 2 * 8
+	q = q + t3
+L7:	t4 = 1 * 8
+	t5 = q - t4
+	*t5 = 1.5
+L8:	p = null
+L9:	iffalse p != null goto L2
+L10:	i = *p
+L2:"#,
+    ),
+    (
+      r#"{ int i; float f; char c; int* p;
+        f = i + f; i = (int) f; c = (char) i; i = -c; f = f * 2 + i; p = (int*) i; }"#,
+      r#"L1:	t1 = (float) i
+	f = t1 + f
+L3:	i = (int) f
+L4:	c = (char) i
+L5:	t2 = (int) c
+	i = minus t2
+L6:	t3 = f * 2
+	t4 = (float) i
+	f = t3 + t4
+L7:	p = (int*) i
+L2:"#,
+    ),
+    (
+      "{ int i; char c; float f; bool b; b = i < 1.5; if (c == 65) f = 1; while (c != f) c = c; }",
+      r#"L1:	t2 = (float) i
+	iffalse t2 < 1.5 goto L4
+	t1 = true
+	goto L5
+L4:	t1 = false
+L5:	b = t1
+L3:	t3 = (int) c
+	iffalse t3 == 65 goto L6
+L7:	f = 1
+L6:	t4 = (float) c
+	iffalse t4 != f goto L2
+L8:	c = c
+	goto L6
+L2:"#,
+    ),
+    (
+      r#"{ int i; int j; float f; int[10] a; int* p;
+        i += 2; a[i + 1] += 3; a[j]--; p++; *p *= 2; j = i++; j = a[i++]--; i /= f; }"#,
+      r#"L1:	i = i + 2
+L3:	t1 = i + 1
+	t2 = t1 * 4
+	t3 = a [ t2 ]
+	t4 = t3 + 3
+	a [ t2 ] = t4
+L4:	t5 = j * 4
+	t6 = a [ t5 ]
+	t7 = t6 - 1
+	a [ t5 ] = t7
+L5:	t8 = 1 * 4
+	p = p + t8
+L6:	t9 = *p
+	t10 = t9 * 2
+	*p = t10
+L7:	t11 = i
+	i = i + 1
+	j = t11
+L8:	t12 = i
+	i = i + 1
+	t13 = t12 * 4
+	t14 = a [ t13 ]
+	t15 = t14 - 1
+	a [ t13 ] = t15
+	j = t14
+L9:	t16 = (float) i
+	t17 = t16 / f
+	i = (int) t17
+L2:"#,
+    ),
+    (
+      "{ int i; int j; float f; bool b; i = i > j ? i : j + 1; f = b || i < 0 ? 1.5 : i; i = b ? 1 : b ? 2 : 3; }",
+      r#"L1:	iffalse i > j goto L4
+	t1 = i
+	goto L5
+L4:	t1 = j + 1
+L5:	i = t1
+L3:	if b goto L9
+	iffalse i < 0 goto L7
+L9:	t2 = 1.5
+	goto L8
+L7:	t2 = (float) i
+L8:	f = t2
+L6:	iffalse b goto L10
+	t3 = 1
+	goto L11
+L10:	iffalse b goto L12
+	t4 = 2
+	goto L13
+L12:	t4 = 3
+L13:	t3 = t4
+L11:	i = t3
+L2:"#,
+    ),
+    (
+      r#"{ int a; int b; float f; int[10] arr; int x; bool c;
+        a = b = 0; f = a = 2; arr[a] = arr[b + 1] = x = 3;
+        while ((x = x - 1) > 0) a = a + x; if (c = a < b) a = 1; }"#,
+      r#"L1:	b = 0
+	a = b
+L3:	a = 2
+	f = (float) a
+L4:	t1 = a * 4
+	t2 = b + 1
+	t3 = t2 * 4
+	x = 3
+	arr [ t3 ] = x
+	arr [ t1 ] = x
+L5:	x = x - 1
+	iffalse x > 0 goto L6
+L7:	a = a + x
+	goto L5
+L6:	iffalse a < b goto L9
+	t4 = true
+	goto L10
+L9:	t4 = false
+L10:	c = t4
+	iffalse c goto L2
+L8:	a = 1
+L2:"#,
+    ),
+    (
+      r#"{ const int N = 10; const float PI = 3.14; const int M = N * 2 + 1; const char C = 65;
+        float[N * 2] a; int[M][-(-2)] b; int i; float f;
+        i = N; f = PI * i; a[N - 1] = PI; switch (i) { case N: i = C; case M + 1: i = 0; } }"#,
+      r#"L1:	i = 10
+L3:	t1 = (float) i
+	f = 3.14 * t1
+L4:	t2 = 10 - 1
+	t3 = t2 * 8
+	a [ t3 ] = 3.14
+L5:	if i == 10 goto L6
+	if i == 22 goto L7
+	goto L2
+L6:	i = 65
+	goto L2
+L7:	i = 0
+L2:"#,
+    ),
+    (
+      r#"{ enum Color { Red, Green = 5, Blue }; Color c; Color[3] cs; int i;
+        c = Green; cs[1] = Blue; if (c == Blue) i = 1; i = (int) c + 1; c = (Color) i;
+        switch (c) { case Red: i = 0; case Green: case Blue: i = 1; } }"#,
+      r#"L1:	c = 5
+L3:	t1 = 1 * 4
+	cs [ t1 ] = 6
+L4:	iffalse c == 6 goto L5
+L6:	i = 1
+L5:	t2 = (int) c
+	i = t2 + 1
+L7:	c = (Color) i
+L8:	if c == 0 goto L9
+	if c == 5 goto L10
+	if c == 6 goto L10
+	goto L2
+L9:	i = 0
+	goto L2
+L10:	i = 1
+L2:"#,
+    ),
+    (
+      r#"{ type Matrix = float[2][3]; type Celsius = float; type Ptr = Celsius*;
+        Matrix m; Celsius c; Ptr p; float f; int i;
+        c = m[i][i] + 1; f = c; p = &c; *p = f; }"#,
+      r#"L1:	t1 = i * 24
+	t2 = i * 8
+	t3 = t1 + t2
+	t4 = m [ t3 ]
+	c = t4 + 1
+L3:	f = c
+L4:	p = &c
+L5:	*p = f
+L2:"#,
+    ),
+    (
+      r#"{ long l; double d; byte b; unsigned int u; unsigned byte ub; int i; char c; float f;
+        l = i + 10L; d = f * 2.5D; i = b + c; u = u + i; l = u + l; ub = (unsigned byte) 300; f = l; }"#,
+      r#"L1:	t1 = (long) i
+	l = t1 + 10
+L3:	t2 = (double) f
+	d = t2 * 2.5
+L4:	t3 = (char) b
+	t4 = t3 + c
+	i = (int) t4
+L5:	t5 = (unsigned int) i
+	u = u + t5
+L6:	t6 = (long) u
+	l = t6 + l
+L7:	ub = (unsigned byte) 300
+L8:	f = (float) l
+L2:"#,
+    ),
+    ("{ byte b; b = -100; }", "L1:\tb = -100\nL2:"),
+    (
+      r#"{ enum Color { Red, Green }; int i; float[3] a; int* p; Color c;
+        read(i); read(a[i]); read(*p); read(c); print(i, a[1] + 1, i < 3, c); }"#,
+      r#"L1:	i = call read_int, 0
+L3:	t1 = i * 8
+	t2 = call read_float, 0
+	a [ t1 ] = t2
+L4:	t3 = call read_int, 0
+	*p = t3
+L5:	c = call read_int, 0
+L6:	param i
+	call print_int, 1
+	t4 = 1 * 8
+	t5 = a [ t4 ]
+	t6 = t5 + 1
+	param t6
+	call print_float, 1
+	iffalse i < 3 goto L7
+	t7 = true
+	goto L8
+L7:	t7 = false
+L8:	param t7
+	call print_bool, 1
+	param c
+	call print_int, 1
+	call print_line, 0
+L2:"#,
+    ),
+    (
+      "{ int i; bool b;\n  assert(i < 10 && i > 0);\n  assert(b); }",
+      r#"L1:	iffalse i < 10 goto L4
+	if i > 0 goto L3
+L4:	trap 2
+L3:	if b goto L2
+	trap 3
+L2:"#,
+    ),
+    (
+      r#"int total; float[3] table;
+        int add(int x) { total = total + x; return total; }
+        { int i; i = add(2); table[i] = 1.5; }
+        int later(int y) { return y + total; }"#,
+      r#"	global @total, 4
+	global @table, 24
+L1:	param 2
+	i = call add, 1
+L3:	t1 = i * 8
+	@table [ t1 ] = 1.5
+L2:	halt
+add:L4:	@total = @total + x
+L6:	return @total
+L5:	return
+later:L7:	t2 = y + @total
+	return t2
+L8:	return
+"#,
+    ),
+    (
+      r#"{ float[10][10] a; float[10][10] b; float[10] r; int i; int j;
+        a[i] = b[j]; r = a[1]; a = b; if (a[i] != r) i = 1; }"#,
+      r#"L1:	t1 = i * 80
+	t2 = j * 80
+	copy a [ t1 ], b [ t2 ], 80
+L3:	t3 = 1 * 80
+	copy r, a [ t3 ], 80
+L4:	copy a, b, 800
+L5:	t4 = i * 80
+	t5 = equal a [ t4 ], r, 80
+	if t5 goto L2
+L6:	i = 1
+L2:"#,
+    ),
+    (
+      r#"type row = int[4];
+        { int n; int* p; float* q; row* rows; p = new int; *p = 3; q = new float[n + 1];
+          rows = new int[n][4]; delete p; delete q; delete rows; }"#,
+      r#"L1:	p = alloc 4
+L3:	*p = 3
+L4:	t1 = n + 1
+	t2 = t1 * 8
+	q = alloc t2
+L5:	t3 = n * 16
+	rows = alloc t3
+L6:	free p
+L7:	free q
+L8:	free rows
+L2:"#,
+    ),
+    (
+      r#"int root(int x) { int i; i = 0; again: if (i * i >= x) goto done; i++; goto again; done: return i; }
+        { int k; k = 3; { start: k--; if (k > 0) goto start; } }"#,
+      r#"L1:	k = 3
+L3:L5:	k = k - 1
+L4:	iffalse k > 0 goto L2
+L6:	goto L5
+L2:	halt
+root:L7:	i = 0
+L9:L11:	t1 = i * i
+	iffalse t1 >= x goto L10
+L12:	goto L13
+L10:	i = i + 1
+L14:	goto L11
+L15:L13:	return i
+L8:	return
+"#,
+    ),
+    (
+      "{ int[3][4] b; int k; k = len(b, 1) * len(b[2]); }",
+      "L1:\tk = 4 * 4\nL2:",
+    ),
+    (
+      r#"int fill(int n) { int[n] a; int i; i = len(a) - 1; a[i] = len(a, 0); a[i]++; return a[i]; }
+        {}"#,
+      r#"L1:L2:	halt
+fill:L3:	a [ 8 ] = n
+	t1 = n * 4
+	a [ 0 ] = alloca t1
+L5:	t2 = a [ 8 ]
+	i = t2 - 1
+L6:	t3 = a [ 0 ]
+	t4 = i * 4
+	t5 = t3 + t4
+	t6 = a [ 8 ]
+	*t5 = t6
+L7:	t7 = a [ 0 ]
+	t8 = i * 4
+	t9 = t7 + t8
+	t10 = *t9
+	t11 = t10 + 1
+	*t9 = t11
+L8:	t12 = a [ 0 ]
+	t13 = i * 4
+	t14 = t12 + t13
+	t15 = *t14
+	return t15
+L4:	return
+"#,
+    ),
+    (
+      "int get(int n, int m) { int[n][m] a; return a[1][2]; } {}",
+      r#"L1:L2:	halt
+get:L3:	a [ 8 ] = n
+	a [ 12 ] = m
+	t1 = n * 4
+	t2 = m * t1
+	a [ 0 ] = alloca t2
+L5:	t3 = a [ 0 ]
+	t4 = a [ 12 ]
+	t5 = 1 * t4
+	t6 = t5 + 2
+	t7 = t6 * 4
+	t8 = t3 + t7
+	t9 = *t8
+	return t9
+L4:	return
+"#,
+    ),
   ];
 
   for tc in tests {
-    ast::reset_labels();
-    expr::Temp::reset_counter();
+    assert_eq!(compile(tc.0, |_| ()), tc.1);
+  }
+}
+
+#[test]
+fn parser_error_tests() {
+  let tests: Vec<(&str, &str)> = vec![
+    (
+      "{int i; switch (i) { case 1: case 1: ; } }",
+      "Duplicate case 1 near line 1",
+    ),
+    (
+      "{int i; switch (i) { default: ; default: ; } }",
+      "Duplicate default case near line 1",
+    ),
+    (
+      "{float f; switch (f) { case 1: ; } }",
+      "Switch expression should be of integral type",
+    ),
+    (
+      "int f(int a) { return a; } { int i; i = f(1, 2); }",
+      "Wrong number of arguments calling int f(int)",
+    ),
+    (
+      "int f(int a) { return a; } { bool b; int i; i = f(b); }",
+      "Type error calling int f(int)",
+    ),
+    (
+      "{ int i; i = g(1); }",
+      "Undeclared function g near line 1",
+    ),
+    (
+      "{ return 1; }",
+      "Return outside of a function near line 1",
+    ),
+    (
+      "int f(int a) { return a; } int f(int b) { return b; } {}",
+      "Function f redefined near line 1",
+    ),
+    (
+      "{ record { int x; } p; p.y = 1; }",
+      "Unknown field y near line 1",
+    ),
+    (
+      "{ record { int x; float x; } p; }",
+      "Duplicate field x near line 1",
+    ),
+    (
+      "{ record { int x; } p; record { int x; } q; p = q; }",
+      "Type error mixing record { int x; } and record { int x; }",
+    ),
+    (
+      "{ int* p; int i; p = i; }",
+      "Type error mixing int* and int",
+    ),
+    (
+      "{ int* p; int i; i = p * 2; }",
+      "Type error mixing int* and int",
+    ),
+    (
+      "{ int* p; float* q; p = q; }",
+      "Type error mixing int* and float*",
+    ),
+    (
+      "{ int i; i = *i; }",
+      "Type error",
+    ),
+    (
+      "{ int i; bool b; b = i < b; }",
+      "Type error mixing int and bool",
+    ),
+    (
+      "{ bool b; b++; }",
+      "Type error mixing bool and int",
+    ),
+    (
+      "{ int i; i = ++1; }",
+      "Cannot assign to 1",
+    ),
+    (
+      "{ int i; i = i ? 1 : 2; }",
+      "Conditional expression should be of bool type",
+    ),
+    (
+      "{ int i; bool b; i = b ? 1 : b; }",
+      "Type error mixing int and bool",
+    ),
+    (
+      "{ int a; a = 1 = 2; }",
+      "Cannot assign to 1",
+    ),
+    (
+      "{ enum Color { Red }; enum Shape { Circle }; Color c; c = Circle; }",
+      "Type error mixing Color and Shape",
+    ),
+    (
+      "{ enum Color { Red }; enum Shape { Circle }; bool b; b = Red == Circle; }",
+      "Type error mixing Color and Shape",
+    ),
+    (
+      "{ enum Color { Red }; Color c; c = 1; }",
+      "Type error mixing Color and int",
+    ),
+    (
+      "{ enum Color { Red }; int i; i = Red + 1; }",
+      "Type error mixing Color and int",
+    ),
+    (
+      "{ enum Color { Red, Green }; Color c; switch (c) { case Red: c = Red; case Green: case Red: c = Green; } }",
+      "Duplicate case Red near line 1",
+    ),
+    (
+      "{ enum Color { Red }; enum Shape { Circle }; Color c; switch (c) { case Circle: c = Red; } }",
+      "Type error mixing Color and Shape near line 1",
+    ),
+    (
+      "{ enum Color { Red, Red }; }",
+      "Duplicate enumerator Red near line 1",
+    ),
+    (
+      "{ type Row = float[3]; Row r; int i; i = r; }",
+      "Type error mixing int and Row",
+    ),
+    (
+      "{ type Row = float[3]; Row r; int i; i = (int) r; }",
+      "Cannot cast Row to int",
+    ),
+    (
+      "{ unsigned float f; }",
+      "Invalid unsigned type float near line 1",
+    ),
+    (
+      "{ long l; l = 1.5L; }",
+      "Invalid suffix L for 1.5 near line 1",
+    ),
+    (
+      "{ int* p; print(p); }",
+      "Cannot print a value of type int*",
+    ),
+    (
+      "{ float[2] a; read(a); }",
+      "Cannot read a value of type [2]float",
+    ),
+    (
+      "{ int i; assert(i); }",
+      "Assert condition should be of bool type",
+    ),
+    (
+      "{ int n; record { float[n] x; } r; }",
+      "Expected an integral constant near line 1",
+    ),
+    (
+      "int f(float x) { int[x] a; return 0; } {}",
+      "Array size of a should be integral",
+    ),
+    (
+      "int f(int n) { int[n][n] a; a[1] = 2; return 0; } {}",
+      "Array a needs 2 subscripts near line 1",
+    ),
+    (
+      "{ float[10] r; int[10] s; r = s; }",
+      "Type error mixing [10]float and [10]int",
+    ),
+    (
+      "{ float[10] r; float[10] s; bool e; e = r < s; }",
+      "Type error",
+    ),
+    (
+      "{ int i; delete i; }",
+      "Cannot delete a value of type int",
+    ),
+    (
+      "{ int* p; p = new int[4611686018427387904L]; }",
+      "Array size too large",
+    ),
+    (
+      "{ int* p; p = new int[0]; }",
+      "Array size should be positive near line 1",
+    ),
+    (
+      "{ int* p; p = new int[-3]; }",
+      "Array size should be positive near line 1",
+    ),
+    (
+      "{ int* p; float f; p = new int[f]; }",
+      "Number of int values should be integral",
+    ),
+    (
+      "{ goto out; }",
+      "Undefined label out near line 1",
+    ),
+    (
+      "{ int i; again: i = 1; again: i = 2; }",
+      "Duplicate label again near line 1",
+    ),
+    (
+      "{ int i; goto inner; { int j; inner: j = 1; } }",
+      "Jump to label inner skips declarations near line 1",
+    ),
+    (
+      "{ int i; int k; k = len(i); }",
+      "len expects an array near line 1",
+    ),
+    (
+      "{ int[3][4] b; int k; k = len(b, 2); }",
+      "b has no dimension 2 near line 1",
+    ),
+    (
+      "{ const int N = 2; float[N - 3] a; }",
+      "Array size should be positive near line 1",
+    ),
+    (
+      "{ const long N = 5000000000L; int[N] a; }",
+      "Array size too large near line 1",
+    ),
+    (
+      "{ int[2000000000][2] a; }",
+      "Array size too large near line 1",
+    ),
+    (
+      "enum E { A = 9223372036854775807L, B }; {}",
+      "Enumerator A does not fit in int near line 1",
+    ),
+    (
+      "enum E { A = 3000000000L }; { E e; e = A; }",
+      "Enumerator A does not fit in int near line 1",
+    ),
+    (
+      "enum E { A = 2147483647, B }; {}",
+      "Enumerator B does not fit in int near line 1",
+    ),
+    (
+      "int f(int a) { a = 1; } {}",
+      "Function f can end without returning a value near line 1",
+    ),
+    (
+      "int f(int a) { if (a < 1) return 1; } {}",
+      "Function f can end without returning a value near line 1",
+    ),
+    (
+      "{ int i; const int N = i; }",
+      "Initializer of N is not a constant near line 1",
+    ),
+    (
+      "{ byte b; b = 300; }",
+      "Constant 300 does not fit in byte",
+    ),
+    (
+      "{ byte b; b = -200; }",
+      "Constant -200 does not fit in byte",
+    ),
+    (
+      "{ byte b; b = 200 + 100; }",
+      "Constant 300 does not fit in byte",
+    ),
+    (
+      "{ int i; i = 3000000000; }",
+      "Constant 3000000000 does not fit in int",
+    ),
+    (
+      "{ int i; i = 9223372036854775807 + 1; }",
+      "Constant 9223372036854775807 does not fit in int",
+    ),
+    (
+      "{ const byte B = 200 + 100; }",
+      "Constant 300 does not fit in byte near line 1",
+    ),
+    (
+      "{ const int N = 1 / 0; }",
+      "Division by zero in constant expression near line 1",
+    ),
+    (
+      "{ const long N = 9000000000000000000L * 2L; }",
+      "Overflow in constant expression near line 1",
+    ),
+    (
+      "{ const int N = 2147483647 + 1; }",
+      "Overflow in constant expression near line 1",
+    ),
+    (
+      "{ const byte A = 100; const int N = A + A; }",
+      "Overflow in constant expression near line 1",
+    ),
+    (
+      "{ int[65536 * 65536 + 1] a; }",
+      "Overflow in constant expression",
+    ),
+    (
+      "{ const int N = 1; N = 2; }",
+      "N is not a variable",
+    ),
+    (
+      "{ int i; bool b; b = (bool) i; }",
+      "Cannot cast int to bool",
+    ),
+    (
+      "{ float f; float* p; p = (float*) f; }",
+      "Cannot cast float to float*",
+    ),
+  ];
 
+  for tc in tests {
     let lexer = lexer::Lexer::new(
       BufReader::new(StringReader::new(tc.0))
     );
     let mut parser = Parser::new(lexer).expect("Creating parser");
 
     let mut str = String::new();
-    parser.program(&mut str).expect("Parsing program");
+    let err = parser.program(&mut str).expect_err("Parsing should fail");
+    assert_eq!(err, tc.1);
+  }
+}
 
-    assert_eq!(str, tc.1);
+#[test]
+fn bounds_check_tests() {
+  let tests: Vec<(&str, &str)> = vec![
+    ("{ int[4] a; a[3] = 1; }", "L1:\tt1 = 3 * 4\n\ta [ t1 ] = 1\nL2:"),
+    (
+      r#"{ int i; int[4] a;
+        a[4] = a[i]; }"#,
+      r#"L1:	if 4 < 0 goto L3
+	if 4 >= 4 goto L3
+	goto L4
+L3:	trap a, 2
+L4:	t1 = 4 * 4
+	if i < 0 goto L5
+	if i >= 4 goto L5
+	goto L6
+L5:	trap a, 2
+L6:	t2 = i * 4
+	t3 = a [ t2 ]
+	a [ t1 ] = t3
+L2:"#,
+    ),
+    (
+      "{ int i; int[4] a;\n a[i]\n = 2; }",
+      r#"L1:	if i < 0 goto L3
+	if i >= 4 goto L3
+	goto L4
+L3:	trap a, 2
+L4:	t1 = i * 4
+	a [ t1 ] = 2
+L2:"#,
+    ),
+    (
+      "int get(int n, int i) { int[n] a; return a[i]; } {}",
+      r#"L1:L2:	halt
+get:L3:	a [ 8 ] = n
+	t1 = n * 4
+	a [ 0 ] = alloca t1
+L5:	t2 = a [ 0 ]
+	t3 = a [ 8 ]
+	if i < 0 goto L6
+	if i >= t3 goto L6
+	goto L7
+L6:	trap a, 1
+L7:	t4 = i * 4
+	t5 = t2 + t4
+	t6 = *t5
+	return t6
+L4:	return
+"#,
+    ),
+  ];
+
+  for tc in tests {
+    assert_eq!(compile(tc.0, |parser| parser.check_bounds(true)), tc.1);
+  }
+}
+
+#[test]
+fn value_comparison_tests() {
+  let tests: Vec<(&str, &str)> = vec![
+    (
+      "{ int i; int j; bool a; bool c; a = i == j; c = a && i < j; c = !(a || c); if (a && c) i = 1; }",
+      r#"L1:	a = i == j
+L3:	t1 = i < j
+	c = a && t1
+L4:	t2 = a || c
+	c = ! t2
+L5:	iffalse a goto L2
+	iffalse c goto L2
+L6:	i = 1
+L2:"#,
+    ),
+    (
+      "{ int* p; bool c; c = p != null && *p > 0; }",
+      r#"L1:	iffalse p != null goto L3
+	t2 = *p
+	iffalse t2 > 0 goto L3
+	t1 = true
+	goto L4
+L3:	t1 = false
+L4:	c = t1
+L2:"#,
+    ),
+  ];
+
+  for tc in tests {
+    assert_eq!(compile(tc.0, |parser| parser.compare_values(true)), tc.1);
+  }
+}
+
+#[test]
+fn interleaved_compilation_tests() {
+  // Each compilation numbers its labels and temporaries on its own, even when
+  // another one is under way on the same thread.
+  let program = "{ int i; i = 0; while (i < 10) i = i * 2 + 1; }";
+  let expected = r#"L1:	i = 0
+L3:	iffalse i < 10 goto L2
+L4:	t1 = i * 2
+	i = t1 + 1
+	goto L3
+L2:"#;
+
+  let mut compilations: Vec<_> = (0..2).map(|_| {
+    let lexer = lexer::Lexer::new(BufReader::new(StringReader::new(program)));
+    let mut parser = Parser::new(lexer).expect("Creating parser");
+    let stm = parser.block().expect("Parsing program");
+    let mut cx = ast::CodegenContext::new();
+    let (begin, after) = (cx.new_label(), cx.new_label());
+    (stm, cx, begin, after)
+  }).collect();
+  for (stm, cx, begin, after) in compilations.iter_mut() {
+    cx.emit_label(*begin);
+    stm.generate(cx, *begin, *after).expect("Generating code");
+    cx.emit_label(*after);
+  }
+  for (_, cx, _, _) in compilations {
+    let mut str = String::new();
+    ast::ir::print(&cx.into_code(), &mut str);
+    assert_eq!(str, expected);
+  }
+}
+
+#[test]
+fn module_tests() {
+  let dir = std::env::temp_dir().join(format!("dragon_module_tests_{}", std::process::id()));
+  fs::create_dir_all(&dir).expect("Creating module directory");
+  let files = vec![
+    ("util.dr", "export int twice(int x) { return x + x; }"),
+    ("geometry.dr", r#"import util;
+      export const int SIDES = 4;
+      export enum Shape { Circle, Square = 3 };
+      export type Vec = float[3];
+      int dbl(int x) { return util.twice(x); }
+      export int area(int s) { return dbl(s) * s; }"#),
+    ("counter.dr", "export int count; int[4] hidden; export int bump(int by) { hidden[1] = by; return count; }"),
+    ("a.dr", "import b;"),
+    ("b.dr", "import a;"),
+  ];
+  for (name, source) in files {
+    fs::write(dir.join(name), source).expect("Writing module");
+  }
+
+  let tests: Vec<(&str, Result<&str, &str>)> = vec![
+    (
+      r#"import geometry; import util;
+        { geometry.Shape s; geometry.Vec v; int i;
+          s = geometry.Square; i = geometry.area(geometry.SIDES) + util.twice(2); }"#,
+      Ok(r#"L1:	s = 3
+L3:	param 4
+	t1 = call geometry.area, 1
+	param 2
+	t2 = call util.twice, 1
+	i = t1 + t2
+L2:	halt
+util.twice:L4:	t3 = x + x
+	return t3
+L5:	return
+geometry.dbl:L6:	param x
+	t4 = call util.twice, 1
+	return t4
+L7:	return
+geometry.area:L8:	param s
+	t5 = call geometry.dbl, 1
+	t6 = t5 * s
+	return t6
+L9:	return
+"#),
+    ),
+    (
+      "import util; { int util; util = 1; }",
+      Ok(r#"L1:	util = 1
+L2:	halt
+util.twice:L3:	t1 = x + x
+	return t1
+L4:	return
+"#),
+    ),
+    ("import geometry; { int i; i = geometry.dbl(1); }", Err("dbl is not exported by geometry near line 1")),
+    (
+      "import counter; { int i; counter.count = 2; i = counter.bump(counter.count); }",
+      Ok(r#"	global @counter.count, 4
+	global @counter.hidden, 16
+L1:	@counter.count = 2
+L3:	param @counter.count
+	i = call counter.bump, 1
+L2:	halt
+counter.bump:L4:	t1 = 1 * 4
+	@counter.hidden [ t1 ] = by
+L6:	return @counter.count
+L5:	return
+"#),
+    ),
+    ("import counter; { counter.hidden[0] = 1; }", Err("hidden is not exported by counter near line 1")),
+    ("import missing; { }", Err("Cannot find module missing near line 1")),
+    ("import a; { }", Err("In module a: In module b: Import cycle a -> b -> a near line 1")),
+  ];
+
+  for tc in tests {
+    let path = dir.join("main.dr");
+    fs::write(&path, tc.0).expect("Writing program");
+    let lexer = lexer::Lexer::new(BufReader::new(File::open(&path).expect("Opening program")));
+    let mut parser = Parser::for_file(lexer, &path).expect("Creating parser");
+
+    let mut str = String::new();
+    match tc.1 {
+      Ok(expected) => {
+        parser.program(&mut str).expect("Parsing program");
+        assert_eq!(str, expected);
+      },
+      Err(expected) => {
+        let err = parser.program(&mut str).expect_err("Parsing should fail");
+        assert_eq!(err, expected);
+      }
+    }
   }
+  fs::remove_dir_all(&dir).expect("Removing module directory");
 }
 }